@@ -0,0 +1,37 @@
+use aoc_2019::computer::{self, Interpreter, RunStatus};
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: intcode-ascii <program>");
+            process::exit(2);
+        }
+    };
+    let program = match fs::read_to_string(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|text| computer::parse_program(&text))
+    {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        }
+    };
+
+    let mut interpreter = Interpreter::new(program);
+    let stdin = io::stdin();
+    match interpreter.run_ascii_interactive(stdin.lock(), io::stdout()) {
+        Ok(RunStatus::Halted) => {}
+        Ok(RunStatus::NeedsInput) => eprintln!("program is still waiting for input"),
+        Ok(status) => eprintln!("program stopped: {:?}", status),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use aoc_2019::computer::debugger::Debugger;
+use aoc_2019::computer::{self, Interpreter};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+/// Instructions that can be stepped back through.
+const HISTORY: usize = 1_000_000;
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: intcode-debugger <program>");
+            process::exit(2);
+        }
+    };
+    let program = match fs::read_to_string(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|text| computer::parse_program(&text))
+    {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        }
+    };
+
+    let mut debugger = Debugger::new(Interpreter::new(program).with_history(HISTORY));
+    let mut last_command = String::new();
+    prompt();
+    for line in io::stdin().lock().lines() {
+        let line = line.unwrap();
+        // An empty line repeats the previous command, handy for stepping
+        let command = if line.trim().is_empty() {
+            last_command.clone()
+        } else {
+            line.trim().to_string()
+        };
+        if command == "q" || command == "quit" {
+            break;
+        }
+        match debugger.command(&command) {
+            Ok(text) if text.is_empty() => {}
+            Ok(text) => println!("{}", text),
+            Err(err) => println!("error: {}", err),
+        }
+        last_command = command;
+        prompt();
+    }
+}
+
+fn prompt() {
+    print!("(icdb) ");
+    io::stdout().flush().unwrap();
+}
@@ -0,0 +1,19 @@
+use aoc_2019::fuzz;
+use std::env;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (seed, runs) = match &args[..] {
+        [] => (1, 10_000),
+        [seed] => (seed.parse().unwrap_or(1), 10_000),
+        [seed, runs, ..] => (seed.parse().unwrap_or(1), runs.parse().unwrap_or(10_000)),
+    };
+    match fuzz::fuzz(seed, runs) {
+        None => println!("{} programs, no divergence", runs),
+        Some(divergence) => {
+            println!("divergence found with seed {}:\n{}", seed, divergence);
+            process::exit(1);
+        }
+    }
+}
@@ -1,4 +1,330 @@
-use std::collections::VecDeque;
+use cycle::LoopDetector;
+use extension::Extension;
+use history::History;
+use profile::Profile;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use trace::Tracer;
+
+pub mod ascii;
+pub mod assembler;
+pub mod cfg;
+mod cycle;
+pub mod debugger;
+pub mod device;
+pub mod disassembler;
+pub mod extension;
+#[cfg(test)]
+mod fixtures;
+mod history;
+pub mod network;
+pub mod profile;
+pub mod runtime;
+pub mod snapshot;
+pub mod symbolic;
+pub mod trace;
+pub mod word;
+
+pub use device::{InputSource, OutputSink};
+pub use trace::TraceFormat;
+pub use word::Word;
+
+/// Errors raised while decoding or executing an Intcode program. Every
+/// variant carries the instruction pointer and the raw opcode of the
+/// instruction that failed.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum InterpreterError<W = i64> {
+    UnknownOpcode {
+        pointer: usize,
+        opcode: W,
+    },
+    /// The mode digit of parameter `param`, counted from 1, is not a known
+    /// mode, or is not 0 for a parameter the opcode doesn't take.
+    InvalidMode {
+        pointer: usize,
+        opcode: W,
+        param: usize,
+        mode: i64,
+    },
+    /// Parameter `param`, counted from 1, is written to but is in immediate
+    /// mode.
+    ImmediateWrite {
+        pointer: usize,
+        opcode: W,
+        param: usize,
+    },
+    NegativeAddress {
+        pointer: usize,
+        opcode: W,
+        address: W,
+    },
+    AddressOutOfRange {
+        pointer: usize,
+        opcode: W,
+        address: W,
+    },
+    InputExhausted {
+        pointer: usize,
+        opcode: W,
+    },
+    /// Raised by `execute_program` when a limit is reached. The machine is
+    /// left before the instruction at `pointer` and can be resumed, after
+    /// `Interpreter::raise_instruction_limit` for the instruction limit.
+    LimitReached {
+        pointer: usize,
+        opcode: W,
+        limit: Limit,
+    },
+    /// Raised by `execute_program` when loop detection is on and the program
+    /// got stuck in a loop, see `RunStatus::InfiniteLoop`.
+    InfiniteLoop {
+        pointer: usize,
+        opcode: W,
+        entry: usize,
+        period: u64,
+    },
+    /// The result of an `Add` or `Mul`, or a relative address, doesn't fit
+    /// in a word, see `Overflow`.
+    Overflow {
+        pointer: usize,
+        opcode: W,
+    },
+    /// Returned by the handler of an instruction registered with
+    /// `Interpreter::with_extension`.
+    Extension {
+        pointer: usize,
+        opcode: W,
+        message: String,
+    },
+}
+
+impl<W: fmt::Display> fmt::Display for InterpreterError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterpreterError::UnknownOpcode { pointer, opcode } => {
+                write!(f, "unknown opcode {} at {}", opcode, pointer)
+            }
+            InterpreterError::InvalidMode {
+                pointer,
+                opcode,
+                param,
+                mode,
+            } => write!(
+                f,
+                "invalid mode {} for parameter {} in opcode {} at {}",
+                mode, param, opcode, pointer
+            ),
+            InterpreterError::ImmediateWrite {
+                pointer,
+                opcode,
+                param,
+            } => write!(
+                f,
+                "immediate mode write parameter {} in opcode {} at {}",
+                param, opcode, pointer
+            ),
+            InterpreterError::NegativeAddress {
+                pointer,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "negative address {} used by opcode {} at {}",
+                address, opcode, pointer
+            ),
+            InterpreterError::AddressOutOfRange {
+                pointer,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "address {} used by opcode {} at {} exceeds the memory limit",
+                address, opcode, pointer
+            ),
+            InterpreterError::InputExhausted { pointer, opcode } => {
+                write!(f, "expected input for opcode {} at {}", opcode, pointer)
+            }
+            InterpreterError::LimitReached {
+                pointer,
+                opcode,
+                limit,
+            } => write!(
+                f,
+                "{} limit reached before opcode {} at {}",
+                limit, opcode, pointer
+            ),
+            InterpreterError::InfiniteLoop {
+                pointer,
+                opcode,
+                entry,
+                period,
+            } => write!(
+                f,
+                "infinite loop entered at {} repeating every {} instructions, stopped before opcode {} at {}",
+                entry, period, opcode, pointer
+            ),
+            InterpreterError::Overflow { pointer, opcode } => {
+                write!(f, "overflow in opcode {} at {}", opcode, pointer)
+            }
+            InterpreterError::Extension {
+                pointer,
+                opcode,
+                message,
+            } => write!(f, "{} in opcode {} at {}", message, opcode, pointer),
+        }
+    }
+}
+
+impl<W: fmt::Debug + fmt::Display> error::Error for InterpreterError<W> {}
+
+/// Why a call to `Interpreter::run` returned. The machine can be resumed by
+/// calling `run` again, after adding input when it reports `NeedsInput`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RunStatus<W = i64> {
+    Halted,
+    NeedsInput,
+    Output(W),
+    LimitReached(Limit),
+    /// The machine came back to a state it was already in without reading
+    /// input or writing output in between, so it will repeat the same
+    /// `period` instructions forever. `entry` is the address of the first
+    /// instruction of the loop, where the machine entered it.
+    InfiniteLoop {
+        entry: usize,
+        period: u64,
+    },
+}
+
+/// A limit set with `Interpreter::with_instruction_limit` or
+/// `Interpreter::with_time_limit`, or the step count of a call to
+/// `Interpreter::run_steps`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Limit {
+    Instructions,
+    Time,
+    Steps,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Instructions => write!(f, "instruction"),
+            Limit::Time => write!(f, "time"),
+            Limit::Steps => write!(f, "step"),
+        }
+    }
+}
+
+/// How strictly parameter modes are checked when decoding an instruction.
+/// Mode digits that are not 0, 1 or 2 are always an error.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DecodeMode {
+    /// Write parameters in immediate mode and nonzero mode digits for
+    /// parameters the opcode doesn't take are errors. The default.
+    Strict,
+    /// Write parameters in immediate mode are treated as positions and the
+    /// mode digits of parameters the opcode doesn't take are ignored.
+    Permissive,
+}
+
+/// The instructions and parameter modes a program may use, named after the
+/// puzzle day that introduced them. Other opcodes are unknown and other mode
+/// digits invalid.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Isa {
+    /// Add, multiply and halt, with position mode parameters only.
+    Day2,
+    /// Adds input, output, jumps, comparisons and immediate mode.
+    Day5,
+    /// Adds relative mode and adjusting the relative base. The default.
+    Day9,
+}
+
+impl Isa {
+    fn has_opcode(self, opcode: i64) -> bool {
+        match self {
+            Isa::Day2 => matches!(opcode, 1 | 2 | 99),
+            Isa::Day5 => matches!(opcode, 1..=8 | 99),
+            Isa::Day9 => matches!(opcode, 1..=9 | 99),
+        }
+    }
+
+    fn has_mode(self, mode: i64) -> bool {
+        match self {
+            Isa::Day2 => mode == 0,
+            Isa::Day5 => mode == 0 || mode == 1,
+            Isa::Day9 => (0..=2).contains(&mode),
+        }
+    }
+}
+
+/// What `Add`, `Mul` and adjusting the relative base do when the result
+/// doesn't fit in a word. A relative address that doesn't fit is always an
+/// error.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Overflow {
+    /// Wraps around in two's complement.
+    Wrap,
+    /// Fails with `InterpreterError::Overflow`. The default.
+    Trap,
+    /// Clamps to the largest or smallest word.
+    Saturate,
+}
+
+impl Overflow {
+    fn add<W: Word>(self, a: &W, b: &W) -> Result<W, Fault<W>> {
+        match self {
+            Overflow::Wrap => Ok(a.wrapping_add(b)),
+            Overflow::Trap => a.checked_add(b).ok_or(Fault::Overflow),
+            Overflow::Saturate => Ok(a.saturating_add(b)),
+        }
+    }
+
+    fn mul<W: Word>(self, a: &W, b: &W) -> Result<W, Fault<W>> {
+        match self {
+            Overflow::Wrap => Ok(a.wrapping_mul(b)),
+            Overflow::Trap => a.checked_mul(b).ok_or(Fault::Overflow),
+            Overflow::Saturate => Ok(a.saturating_mul(b)),
+        }
+    }
+}
+
+/// Where a single run stops: the instruction count and the time at which
+/// its limits are reached. The instruction count is the same for every run.
+pub(crate) struct Budget {
+    instructions: Option<u64>,
+    deadline: Option<Instant>,
+}
+
+/// A memory access failure, before it is tied to the instruction that
+/// caused it.
+enum Fault<W> {
+    NegativeAddress(W),
+    AddressOutOfRange(W),
+    Overflow,
+}
+
+impl<W> Fault<W> {
+    fn at(self, pointer: usize, opcode: W) -> InterpreterError<W> {
+        match self {
+            Fault::NegativeAddress(address) => InterpreterError::NegativeAddress {
+                pointer,
+                opcode,
+                address,
+            },
+            Fault::AddressOutOfRange(address) => InterpreterError::AddressOutOfRange {
+                pointer,
+                opcode,
+                address,
+            },
+            Fault::Overflow => InterpreterError::Overflow { pointer, opcode },
+        }
+    }
+}
 
 #[derive(PartialEq, Eq)]
 enum ParamMode {
@@ -6,51 +332,153 @@ enum ParamMode {
     Write,
 }
 
-enum Param {
-    Position(usize),
-    Value(i64),
+#[derive(Clone)]
+enum Param<W = i64> {
+    Position(W),
+    Value(W),
+    Relative(W),
 }
 
-impl Param {
-    fn evaluate(&self, program: &Vec<i64>, mode: ParamMode) -> i64 {
+impl<W: Word> Param<W> {
+    fn evaluate(
+        &self,
+        memory: &mut Memory<W>,
+        relative_base: &W,
+        mode: ParamMode,
+    ) -> Result<W, Fault<W>> {
         match self {
-            Param::Position(pos) if mode == ParamMode::Read => program[*pos],
-            Param::Position(pos) if mode == ParamMode::Write => *pos as i64,
-            Param::Value(val) => *val,
-            _ => unreachable!(),
+            Param::Position(pos) if mode == ParamMode::Read => memory.read(pos.clone()),
+            Param::Position(pos) => Ok(pos.clone()),
+            Param::Relative(offset) => {
+                let address = relative_base.checked_add(offset).ok_or(Fault::Overflow)?;
+                match mode {
+                    ParamMode::Read => memory.read(address),
+                    ParamMode::Write => Ok(address),
+                }
+            }
+            Param::Value(val) if mode == ParamMode::Read => Ok(val.clone()),
+            Param::Value(_) => unreachable!("write parameters are never decoded as immediates"),
         }
     }
 
-    fn evaluate_r(&self, program: &Vec<i64>) -> i64 {
-        self.evaluate(program, ParamMode::Read)
+    fn evaluate_r(&self, memory: &mut Memory<W>, relative_base: &W) -> Result<W, Fault<W>> {
+        self.evaluate(memory, relative_base, ParamMode::Read)
     }
 
-    fn evaluate_w(&self, program: &Vec<i64>) -> i64 {
-        self.evaluate(program, ParamMode::Write)
+    fn evaluate_w(&self, memory: &mut Memory<W>, relative_base: &W) -> Result<W, Fault<W>> {
+        self.evaluate(memory, relative_base, ParamMode::Write)
     }
 
-    fn from(value: i64, param_type: i64) -> Self {
+    fn from(value: W, param_type: i64) -> Option<Self> {
         match param_type {
-            0 => Param::Position(value as usize),
-            1 => Param::Value(value),
-            _ => unreachable!(),
+            0 => Some(Param::Position(value)),
+            1 => Some(Param::Value(value)),
+            2 => Some(Param::Relative(value)),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes the parameters of the instruction at `pointer`, counted from 1.
+struct ParamReader<'a, W> {
+    memory: &'a [W],
+    pointer: usize,
+    decoding: DecodeMode,
+    isa: Isa,
+    opcode: W,
+    /// The opcode without its parameter modes, or -1 for words too large to
+    /// be an opcode.
+    code: i64,
+    modes: [i64; 3],
+}
+
+impl<'a, W: Word> ParamReader<'a, W> {
+    fn new(memory: &'a [W], pointer: usize, decoding: DecodeMode, isa: Isa) -> Self {
+        let opcode = memory
+            .get(pointer)
+            .cloned()
+            .unwrap_or_else(|| W::from_i64(0));
+        let (code, t1, t2, t3) = Interpreter::parse_opcode(opcode.to_i64().unwrap_or(-1));
+        Self {
+            memory,
+            pointer,
+            decoding,
+            isa,
+            opcode,
+            code,
+            modes: [t1, t2, t3],
+        }
+    }
+
+    fn invalid(&self, index: usize) -> InterpreterError<W> {
+        InterpreterError::InvalidMode {
+            pointer: self.pointer,
+            opcode: self.opcode.clone(),
+            param: index,
+            mode: self.modes[index - 1],
+        }
+    }
+
+    fn read(&self, index: usize) -> Result<Param<W>, InterpreterError<W>> {
+        let mode = self.modes[index - 1];
+        let value = self
+            .memory
+            .get(self.pointer + index)
+            .cloned()
+            .unwrap_or_else(|| W::from_i64(0));
+        Param::from(value, mode)
+            .filter(|_| self.isa.has_mode(mode))
+            .ok_or_else(|| self.invalid(index))
+    }
+
+    fn write(&self, index: usize) -> Result<Param<W>, InterpreterError<W>> {
+        match self.read(index)? {
+            Param::Value(value) if self.decoding == DecodeMode::Permissive => {
+                Ok(Param::Position(value))
+            }
+            Param::Value(_) => Err(InterpreterError::ImmediateWrite {
+                pointer: self.pointer,
+                opcode: self.opcode.clone(),
+                param: index,
+            }),
+            param => Ok(param),
+        }
+    }
+
+    /// In strict mode, checks that the instruction has no mode digits past
+    /// its `used` parameters.
+    fn check_unused(&self, used: usize) -> Result<(), InterpreterError<W>> {
+        if self.decoding == DecodeMode::Permissive {
+            return Ok(());
+        }
+        match (used + 1..=self.modes.len()).find(|&index| self.modes[index - 1] != 0) {
+            Some(unused) => Err(self.invalid(unused)),
+            None => Ok(()),
         }
     }
 }
 
-enum Instruction {
-    Add(Param, Param, Param),
-    Mul(Param, Param, Param),
-    Inp(Param),
-    Out(Param),
-    JumpTrue(Param, Param),
-    JumpFalse(Param, Param),
-    LessThan(Param, Param, Param),
-    Equals(Param, Param, Param),
+#[derive(Clone)]
+enum Instruction<W = i64> {
+    Add(Param<W>, Param<W>, Param<W>),
+    Mul(Param<W>, Param<W>, Param<W>),
+    Inp(Param<W>),
+    Out(Param<W>),
+    JumpTrue(Param<W>, Param<W>),
+    JumpFalse(Param<W>, Param<W>),
+    LessThan(Param<W>, Param<W>, Param<W>),
+    Equals(Param<W>, Param<W>, Param<W>),
+    AdjustBase(Param<W>),
     Halt,
+    /// An opcode registered with `Interpreter::with_extension`.
+    Extension {
+        opcode: i64,
+        params: Vec<Param<W>>,
+        roles: Vec<extension::Role>,
+    },
 }
 
-impl Instruction {
+impl<W> Instruction<W> {
     fn instruction_size(&self) -> usize {
         match self {
             Instruction::Add(_, _, _) => 4,
@@ -61,194 +489,763 @@ impl Instruction {
             Instruction::Equals(_, _, _) => 4,
             Instruction::JumpTrue(_, _) => 3,
             Instruction::JumpFalse(_, _) => 3,
+            Instruction::AdjustBase(_) => 2,
             Instruction::Halt => 0,
+            Instruction::Extension { params, .. } => 1 + params.len(),
+        }
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::Add(_, _, _) => "ADD",
+            Instruction::Mul(_, _, _) => "MUL",
+            Instruction::Inp(_) => "IN",
+            Instruction::Out(_) => "OUT",
+            Instruction::JumpTrue(_, _) => "JT",
+            Instruction::JumpFalse(_, _) => "JF",
+            Instruction::LessThan(_, _, _) => "LT",
+            Instruction::Equals(_, _, _) => "EQ",
+            Instruction::AdjustBase(_) => "ARB",
+            Instruction::Halt => "HLT",
+            Instruction::Extension { .. } => "EXT",
+        }
+    }
+
+    fn params(&self) -> Vec<&Param<W>> {
+        match self {
+            Instruction::Add(p1, p2, p3)
+            | Instruction::Mul(p1, p2, p3)
+            | Instruction::LessThan(p1, p2, p3)
+            | Instruction::Equals(p1, p2, p3) => vec![p1, p2, p3],
+            Instruction::JumpTrue(p1, p2) | Instruction::JumpFalse(p1, p2) => vec![p1, p2],
+            Instruction::Inp(p1) | Instruction::Out(p1) | Instruction::AdjustBase(p1) => vec![p1],
+            Instruction::Halt => vec![],
+            Instruction::Extension { params, .. } => params.iter().collect(),
+        }
+    }
+
+    /// Whether the instruction writes to the parameter at `index`.
+    fn writes(&self, index: usize) -> bool {
+        match self {
+            Instruction::Add(_, _, _)
+            | Instruction::Mul(_, _, _)
+            | Instruction::LessThan(_, _, _)
+            | Instruction::Equals(_, _, _) => index == 2,
+            Instruction::Inp(_) => index == 0,
+            Instruction::Extension { roles, .. } => roles[index] == extension::Role::Write,
+            _ => false,
         }
     }
+
+    /// Number of memory cells the instruction occupies, opcode included.
+    fn encoded_size(&self) -> usize {
+        1 + self.params().len()
+    }
+}
+
+impl<W: Word> Instruction<W> {
+    /// The value `Add`, `Mul`, `LessThan` or `Equals` writes for the
+    /// operands `a` and `b`.
+    fn combine(&self, overflow: Overflow, a: &W, b: &W) -> Result<W, Fault<W>> {
+        match self {
+            Instruction::Add(_, _, _) => overflow.add(a, b),
+            Instruction::Mul(_, _, _) => overflow.mul(a, b),
+            Instruction::LessThan(_, _, _) => Ok(W::from_i64((a < b) as i64)),
+            Instruction::Equals(_, _, _) => Ok(W::from_i64((a == b) as i64)),
+            _ => unreachable!("{} doesn't combine two values", self.mnemonic()),
+        }
+    }
+
+    /// Whether `JumpTrue` or `JumpFalse` jumps when its first parameter is
+    /// `condition`.
+    fn jumps(&self, condition: &W) -> bool {
+        match self {
+            Instruction::JumpTrue(_, _) => *condition != W::from_i64(0),
+            Instruction::JumpFalse(_, _) => *condition == W::from_i64(0),
+            _ => unreachable!("{} isn't a jump", self.mnemonic()),
+        }
+    }
+}
+
+/// A memory access made by an instruction, recorded while an access log is
+/// enabled.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Access<W = i64> {
+    Read { address: usize, value: W },
+    Write { address: usize, old: W, new: W },
 }
 
-pub struct Interpreter {
-    input: VecDeque<i64>,
-    pub output: VecDeque<i64>,
-    program: Vec<i64>,
+impl<W> Access<W> {
+    pub fn address(&self) -> usize {
+        match self {
+            Access::Read { address, .. } | Access::Write { address, .. } => *address,
+        }
+    }
+}
+
+/// Intcode memory. Addresses past the end of the loaded program read as 0
+/// and writes to them grow the memory, up to an optional number of cells.
+struct Memory<W = i64> {
+    cells: Vec<W>,
+    limit: Option<usize>,
+    log: Option<Vec<Access<W>>>,
+    /// Hash of `cells`, kept up to date while loop detection is on.
+    hash: Option<u64>,
+    /// Instructions decoded so far by address. A write drops the ones it may
+    /// have changed, so self-modifying code still works.
+    decoded: Vec<Option<Instruction<W>>>,
+    /// The address of the instruction taken out of `decoded` to run, until a
+    /// write to it means it can't go back.
+    taken: Option<usize>,
+}
+
+/// Words taken by the longest instruction.
+const MAX_INSTRUCTION_SIZE: usize = 4;
+
+impl<W: Word> Memory<W> {
+    fn new(cells: Vec<W>) -> Self {
+        Self {
+            cells,
+            limit: None,
+            log: None,
+            hash: None,
+            decoded: Vec::new(),
+            taken: None,
+        }
+    }
+
+    /// Reads a cell without logging the access.
+    fn get(&self, address: W) -> W {
+        to_address(address)
+            .ok()
+            .and_then(|address| self.cells.get(address).cloned())
+            .unwrap_or_else(|| W::from_i64(0))
+    }
+
+    fn read(&mut self, address: W) -> Result<W, Fault<W>> {
+        let address = to_address(address)?;
+        let value = self
+            .cells
+            .get(address)
+            .cloned()
+            .unwrap_or_else(|| W::from_i64(0));
+        if let Some(log) = &mut self.log {
+            log.push(Access::Read {
+                address,
+                value: value.clone(),
+            });
+        }
+        Ok(value)
+    }
+
+    fn write(&mut self, address: W, value: W) -> Result<(), Fault<W>> {
+        let cell = to_address(address.clone())?;
+        if cell >= self.cells.len() {
+            if let Some(limit) = self.limit {
+                if cell >= limit {
+                    return Err(Fault::AddressOutOfRange(address));
+                }
+            }
+            self.cells.resize(cell + 1, W::from_i64(0));
+        }
+        if let Some(log) = &mut self.log {
+            log.push(Access::Write {
+                address: cell,
+                old: self.cells[cell].clone(),
+                new: value.clone(),
+            });
+        }
+        self.restore(cell, value);
+        Ok(())
+    }
+
+    /// Puts back the value of a cell, as when undoing a write.
+    fn restore(&mut self, cell: usize, value: W) {
+        if let Some(hash) = &mut self.hash {
+            *hash ^= cycle::cell_hash(cell, &self.cells[cell]) ^ cycle::cell_hash(cell, &value);
+        }
+        self.cells[cell] = value;
+        self.forget_decoded(cell);
+    }
+
+    /// Drops the cached instructions that may include the word at `cell`.
+    fn forget_decoded(&mut self, cell: usize) {
+        let first = cell.saturating_sub(MAX_INSTRUCTION_SIZE - 1);
+        if self
+            .taken
+            .is_some_and(|taken| first <= taken && taken <= cell)
+        {
+            self.taken = None;
+        }
+        for decoded in self.decoded.iter_mut().take(cell + 1).skip(first) {
+            *decoded = None;
+        }
+    }
+}
+
+/// The address `address` refers to. Words too large for a `usize` are out
+/// of range.
+fn to_address<W: Word>(address: W) -> Result<usize, Fault<W>> {
+    match address.to_i64() {
+        Some(value) if value >= 0 => {
+            usize::try_from(value).map_err(|_| Fault::AddressOutOfRange(address))
+        }
+        Some(_) => Err(Fault::NegativeAddress(address)),
+        None if address < W::from_i64(0) => Err(Fault::NegativeAddress(address)),
+        None => Err(Fault::AddressOutOfRange(address)),
+    }
+}
+
+/// Takes the next input from the input queue, then from the input device.
+fn next_input<W>(
+    input: &mut VecDeque<W>,
+    device: &mut Option<Box<dyn InputSource<W>>>,
+) -> Option<W> {
+    match (input.pop_front(), device) {
+        (Some(value), _) => Some(value),
+        (None, Some(device)) => device.read(),
+        (None, None) => None,
+    }
+}
+
+/// Writes an output to the output device, or to the output queue without one.
+fn send_output<W>(output: &mut VecDeque<W>, device: &mut Option<Box<dyn OutputSink<W>>>, value: W) {
+    match device {
+        Some(device) => device.write(value),
+        None => output.push_back(value),
+    }
+}
+
+/// Parses a program in the usual comma separated puzzle input format.
+pub fn parse_program(text: &str) -> Result<Vec<i64>, String> {
+    text.split(',')
+        .map(|word| {
+            word.trim()
+                .parse::<i64>()
+                .map_err(|_| format!("invalid word {:?}", word.trim()))
+        })
+        .collect()
+}
+
+/// An Intcode machine whose memory holds words of type `W`, see `Word`.
+pub struct Interpreter<W = i64> {
+    input: VecDeque<W>,
+    pub output: VecDeque<W>,
+    /// Outputs queued by an extension instruction that `run` has yet to
+    /// return.
+    pending_output: VecDeque<W>,
+    memory: Memory<W>,
     pointer: usize,
+    relative_base: W,
     halt: bool,
     halt_on_output: bool,
+    input_device: Option<Box<dyn InputSource<W>>>,
+    output_device: Option<Box<dyn OutputSink<W>>>,
+    tracer: Option<Tracer>,
+    executed: u64,
+    instruction_limit: Option<u64>,
+    time_limit: Option<Duration>,
+    loop_detector: Option<LoopDetector<W>>,
+    profile: Option<Profile>,
+    history: Option<History<W>>,
+    decoding: DecodeMode,
+    isa: Isa,
+    overflow: Overflow,
+    extensions: HashMap<i64, Extension<W>>,
 }
 
 impl Interpreter {
     pub fn new(program: Vec<i64>) -> Self {
+        Self::from_words(program)
+    }
+}
+
+impl<W: Word> Interpreter<W> {
+    /// An interpreter for a program of any word type, like `i128`.
+    pub fn from_words(program: Vec<W>) -> Self {
         Self {
             input: VecDeque::new(),
             output: VecDeque::new(),
-            program: program,
+            pending_output: VecDeque::new(),
+            memory: Memory::new(program),
             pointer: 0,
+            relative_base: W::from_i64(0),
             halt: false,
             halt_on_output: false,
+            input_device: None,
+            output_device: None,
+            tracer: None,
+            executed: 0,
+            instruction_limit: None,
+            time_limit: None,
+            loop_detector: None,
+            profile: None,
+            history: None,
+            decoding: DecodeMode::Strict,
+            isa: Isa::Day9,
+            overflow: Overflow::Trap,
+            extensions: HashMap::new(),
         }
     }
 
-    pub fn execute_program(&mut self) {
+    pub fn execute_program(&mut self) -> Result<(), InterpreterError<W>> {
+        let budget = self.budget();
         while !self.halt {
-            self.execute_step();
+            if let Some(limit) = self.limit_reached(&budget) {
+                return Err(InterpreterError::LimitReached {
+                    pointer: self.pointer,
+                    opcode: self.raw_opcode(),
+                    limit,
+                });
+            }
+            if let Some(found) = self.take_loop() {
+                return Err(InterpreterError::InfiniteLoop {
+                    pointer: self.pointer,
+                    opcode: self.raw_opcode(),
+                    entry: found.entry,
+                    period: found.period,
+                });
+            }
+            self.execute_step()?;
         }
 
         self.halt = false;
+        Ok(())
+    }
+
+    /// Runs until the program halts, produces an output or waits for input.
+    /// Outputs are returned instead of being queued, unless an output device
+    /// is attached, in which case they are written to it and the run goes on.
+    pub fn run(&mut self) -> Result<RunStatus<W>, InterpreterError<W>> {
+        self.run_steps(usize::MAX)
+    }
+
+    /// Like `run`, but returns `LimitReached(Limit::Steps)` after executing
+    /// `max_steps` instructions.
+    pub fn run_steps(&mut self, max_steps: usize) -> Result<RunStatus<W>, InterpreterError<W>> {
+        let budget = self.budget();
+        self.run_steps_within(&budget, max_steps)
+    }
+
+    /// Like `run_steps`, but checks the limits against a budget that the
+    /// caller keeps across several calls.
+    pub(crate) fn run_steps_within(
+        &mut self,
+        budget: &Budget,
+        max_steps: usize,
+    ) -> Result<RunStatus<W>, InterpreterError<W>> {
+        if let Some(value) = self.pending_output.pop_front() {
+            return Ok(RunStatus::Output(value));
+        }
+        if self.halt {
+            return Ok(RunStatus::Halted);
+        }
+        for _ in 0..max_steps {
+            if let Some(limit) = self.limit_reached(budget) {
+                return Ok(RunStatus::LimitReached(limit));
+            }
+            if let Some(found) = self.take_loop() {
+                return Ok(RunStatus::InfiniteLoop {
+                    entry: found.entry,
+                    period: found.period,
+                });
+            }
+            let pointer = self.pointer;
+            let instruction = self.take_instruction()?;
+            if let Instruction::Inp(_) = instruction {
+                if !self.has_input() {
+                    self.cache_instruction(pointer, instruction);
+                    return Ok(RunStatus::NeedsInput);
+                }
+            }
+            let queued = self.output.len();
+            let result = self.execute(&instruction);
+            let (output, extension) = match instruction {
+                Instruction::Out(_) => (true, false),
+                Instruction::Extension { .. } => (false, true),
+                _ => (false, false),
+            };
+            self.cache_instruction(pointer, instruction);
+            match result {
+                // The extension was undone and runs again once there is input
+                Err(InterpreterError::InputExhausted { .. }) if extension => {
+                    return Ok(RunStatus::NeedsInput)
+                }
+                result => result?,
+            }
+            if output && self.output_device.is_none() {
+                let value = self.output.pop_back().expect("output expected");
+                if let Some(history) = &mut self.history {
+                    history.output_taken(true);
+                }
+                return Ok(RunStatus::Output(value));
+            }
+            if extension && self.output.len() > queued {
+                self.pending_output = self.output.split_off(queued);
+                let value = self.pending_output.pop_front().expect("output expected");
+                return Ok(RunStatus::Output(value));
+            }
+            if self.halt {
+                return Ok(RunStatus::Halted);
+            }
+        }
+        Ok(RunStatus::LimitReached(Limit::Steps))
+    }
+
+    pub(crate) fn budget(&self) -> Budget {
+        Budget {
+            instructions: self.instruction_limit,
+            deadline: self.time_limit.map(|limit| Instant::now() + limit),
+        }
+    }
+
+    fn limit_reached(&self, budget: &Budget) -> Option<Limit> {
+        if budget
+            .instructions
+            .is_some_and(|last| self.executed >= last)
+        {
+            Some(Limit::Instructions)
+        } else if budget
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            Some(Limit::Time)
+        } else {
+            None
+        }
+    }
+
+    fn take_loop(&mut self) -> Option<cycle::Loop> {
+        self.loop_detector.as_mut()?.found.take()
+    }
+
+    /// Whether an input is ready, pulling one from the input device into the
+    /// queue if needed.
+    fn has_input(&mut self) -> bool {
+        if self.input.is_empty() {
+            if let Some(value) = self.input_device.as_mut().and_then(|device| device.read()) {
+                self.input.push_back(value);
+            }
+        }
+        !self.input.is_empty()
+    }
+
+    /// Reads input from `source` once the input queue is empty.
+    pub fn with_input<S: InputSource<W> + 'static>(mut self, source: S) -> Self {
+        self.input_device = Some(Box::new(source));
+        self
     }
 
-    pub fn add_input(&mut self, input: i64) {
+    /// Writes every output to `sink` instead of the output queue.
+    pub fn with_output<S: OutputSink<W> + 'static>(mut self, sink: S) -> Self {
+        self.output_device = Some(Box::new(sink));
+        self
+    }
+
+    pub fn add_input(&mut self, input: W) {
         self.input.push_back(input);
     }
 
-    pub fn get_output(&mut self) -> Vec<i64> {
+    pub fn get_output(&mut self) -> Vec<W> {
         self.output.iter().cloned().collect()
     }
 
+    pub fn memory(&self) -> &[W] {
+        &self.memory.cells
+    }
+
     pub fn halt_on_output(mut self) -> Self {
         self.halt_on_output = true;
         self
     }
 
-    fn execute_step(&mut self) {
+    /// Stops the machine once it has executed `instructions` more
+    /// instructions, counted across every call to `run`, `run_steps` and
+    /// `execute_program`.
+    pub fn with_instruction_limit(mut self, instructions: u64) -> Self {
+        self.instruction_limit = Some(self.executed.saturating_add(instructions));
+        self
+    }
+
+    /// Lets a machine that reached its instruction limit execute
+    /// `instructions` more.
+    pub fn raise_instruction_limit(&mut self, instructions: u64) {
+        if let Some(limit) = &mut self.instruction_limit {
+            *limit = limit.saturating_add(instructions);
+        }
+    }
+
+    /// Stops each call to `run`, `run_steps` or `execute_program` once it has
+    /// been running for `limit`.
+    pub fn with_time_limit(mut self, limit: Duration) -> Self {
+        self.time_limit = Some(limit);
+        self
+    }
+
+    /// Stops the program once it repeats a previous state without reading
+    /// input or writing output in between. Programs that keep printing aren't
+    /// stuck. This costs a little time on every instruction and a few copies
+    /// of the memory.
+    pub fn with_loop_detection(mut self) -> Self {
+        self.memory.hash = Some(cycle::memory_hash(&self.memory.cells));
+        self.loop_detector = Some(LoopDetector::new());
+        self
+    }
+
+    /// Sets how strictly parameter modes are checked, `DecodeMode::Strict`
+    /// by default.
+    pub fn with_decode_mode(mut self, decoding: DecodeMode) -> Self {
+        self.decoding = decoding;
+        self.memory.decoded.clear();
+        self
+    }
+
+    /// Restricts the program to the instructions and parameter modes of
+    /// `isa`, `Isa::Day9` by default. Extension opcodes are always allowed.
+    pub fn with_isa(mut self, isa: Isa) -> Self {
+        self.isa = isa;
+        self.memory.decoded.clear();
+        self
+    }
+
+    /// Sets what arithmetic does on overflow, `Overflow::Trap` by default.
+    pub fn with_overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// How many instructions have been executed since the machine started.
+    pub fn instructions_executed(&self) -> u64 {
+        self.executed
+    }
+
+    /// Caps how many memory cells the program may grow to.
+    pub fn with_memory_limit(mut self, cells: usize) -> Self {
+        self.memory.limit = Some(cells);
+        self
+    }
+
+    /// Streams a record of every executed instruction to `writer`. Call
+    /// `finish_trace` when done to flush it and see any write error.
+    pub fn with_trace<T: Write + Send + 'static>(mut self, writer: T, format: TraceFormat) -> Self {
+        self.memory.log.get_or_insert_with(Vec::new);
+        self.tracer = Some(Tracer::new(Box::new(writer), format));
+        self
+    }
+
+    pub fn finish_trace(&mut self) -> io::Result<()> {
+        match &mut self.tracer {
+            Some(tracer) => tracer.finish(),
+            None => Ok(()),
+        }
+    }
+
+    fn execute_step(&mut self) -> Result<(), InterpreterError<W>> {
         if !self.halt {
-            let current_instruction = self.parse_current_instruction();
-            self.pointer = self.execute_instruction(&current_instruction);
+            let pointer = self.pointer;
+            let current_instruction = self.take_instruction()?;
+            self.execute(&current_instruction)?;
+            if let Instruction::Out(_) = current_instruction {
+                self.halt = self.halt_on_output;
+            }
+            self.cache_instruction(pointer, current_instruction);
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, instruction: &Instruction<W>) -> Result<(), InterpreterError<W>> {
+        if self.tracer.is_none()
+            && self.profile.is_none()
+            && self.history.is_none()
+            && self.loop_detector.is_none()
+        {
+            self.pointer = self.execute_instruction(instruction)?;
+            self.executed += 1;
+            return Ok(());
+        }
+        let operands = self
+            .tracer
+            .as_ref()
+            .map(|_| instruction.operands(&self.memory, &self.relative_base));
+        let pointer = self.pointer;
+        let opcode = self.raw_opcode();
+        let before = (
+            self.pointer,
+            self.relative_base.clone(),
+            self.halt,
+            self.memory.cells.len(),
+        );
+
+        self.pointer = self.execute_instruction(instruction)?;
+        self.executed += 1;
+
+        self.detect_loop(instruction);
+
+        if let (Some(tracer), Some(operands)) = (&mut self.tracer, operands) {
+            let log = self.memory.log.as_deref().unwrap_or_default();
+            tracer.record(pointer, &opcode, instruction, operands, log);
+        }
+        if let Some(profile) = &mut self.profile {
+            let log = self.memory.log.as_deref().unwrap_or_default();
+            // Executed opcodes always fit
+            profile.record(pointer, opcode.to_i64().unwrap_or_default(), log);
+        }
+        if let Some(history) = &mut self.history {
+            let log = self.memory.log.as_deref().unwrap_or_default();
+            let queued_output =
+                matches!(instruction, Instruction::Out(_)) && self.output_device.is_none();
+            history.record(instruction, before, log, queued_output);
         }
+        Ok(())
     }
 
-    fn execute_instruction(&mut self, instruction: &Instruction) -> usize {
-        let program = &mut self.program;
+    fn execute_instruction(
+        &mut self,
+        instruction: &Instruction<W>,
+    ) -> Result<usize, InterpreterError<W>> {
+        let start = self.pointer;
         let mut pointer = self.pointer;
+        let opcode = self.raw_opcode();
+        if let Some(log) = &mut self.memory.log {
+            log.clear();
+        }
+        let fault = |fault: Fault<W>| fault.at(start, opcode.clone());
+        let memory = &mut self.memory;
+        let relative_base = &self.relative_base.clone();
         match instruction {
-            Instruction::Add(p1, p2, p3) => {
-                let dst_addr = p3.evaluate_w(program) as usize;
-                if dst_addr >= program.len() {
-                    println!("Error at {:?} {:?}", pointer, program);
-                }
-                program[dst_addr] = p1.evaluate_r(program) + p2.evaluate_r(program);
-                pointer += instruction.instruction_size();
-            }
-            Instruction::Mul(p1, p2, p3) => {
-                let dst_addr = p3.evaluate_w(program) as usize;
-                if dst_addr >= program.len() {
-                    println!("Error at {:?} {:?}", pointer, program);
-                }
-                program[dst_addr] = p1.evaluate_r(program) * p2.evaluate_r(program);
+            Instruction::Add(p1, p2, p3)
+            | Instruction::Mul(p1, p2, p3)
+            | Instruction::LessThan(p1, p2, p3)
+            | Instruction::Equals(p1, p2, p3) => {
+                let dst_addr = p3.evaluate_w(memory, relative_base).map_err(fault)?;
+                let result = instruction
+                    .combine(
+                        self.overflow,
+                        &p1.evaluate_r(memory, relative_base).map_err(fault)?,
+                        &p2.evaluate_r(memory, relative_base).map_err(fault)?,
+                    )
+                    .map_err(fault)?;
+                memory.write(dst_addr, result).map_err(fault)?;
                 pointer += instruction.instruction_size();
             }
             Instruction::Inp(p1) => {
-                let input = self
-                    .input
-                    .pop_front()
-                    .expect(&format!("Expected input at {}", self.pointer));
-                let dst_addr = p1.evaluate_w(program) as usize;
-                if dst_addr >= program.len() {
-                    println!("Error at {:?} {:?}", pointer, program);
-                }
-                program[dst_addr] = input;
+                let dst_addr = p1.evaluate_w(memory, relative_base).map_err(fault)?;
+                let input = next_input(&mut self.input, &mut self.input_device).ok_or(
+                    InterpreterError::InputExhausted {
+                        pointer,
+                        opcode: opcode.clone(),
+                    },
+                )?;
+                memory.write(dst_addr, input).map_err(fault)?;
                 pointer += instruction.instruction_size();
             }
             Instruction::Out(p1) => {
-                let out_val = p1.evaluate_r(program);
-                self.output.push_back(out_val);
+                let out_val = p1.evaluate_r(memory, relative_base).map_err(fault)?;
+                send_output(&mut self.output, &mut self.output_device, out_val);
                 pointer += instruction.instruction_size();
-                if self.halt_on_output {
-                    self.halt = true;
-                }
-            }
-            Instruction::JumpTrue(p1, p2) => {
-                if p1.evaluate_r(program) != 0 {
-                    let dst = p2.evaluate_r(program) as usize;
-                    pointer = dst;
-                    if dst >= program.len() {
-                        println!("Error at {:?} {:?}", pointer, program);
-                    }
-                } else {
-                    pointer += instruction.instruction_size();
-                }
             }
-            Instruction::JumpFalse(p1, p2) => {
-                if p1.evaluate_r(program) == 0 {
-                    let dst = p2.evaluate_r(program) as usize;
-                    pointer = dst;
-                    if dst >= program.len() {
-                        println!("Error at {:?} {:?}", pointer, program);
-                    }
+            Instruction::JumpTrue(p1, p2) | Instruction::JumpFalse(p1, p2) => {
+                if instruction.jumps(&p1.evaluate_r(memory, relative_base).map_err(fault)?) {
+                    let dst = p2.evaluate_r(memory, relative_base).map_err(fault)?;
+                    pointer = to_address(dst).map_err(fault)?;
                 } else {
                     pointer += instruction.instruction_size();
                 }
             }
-            Instruction::LessThan(p1, p2, p3) => {
-                let dst_addr = p3.evaluate_w(program) as usize;
-                if dst_addr >= program.len() {
-                    println!("Error at {:?} {:?}", pointer, program);
-                }
-                if p1.evaluate_r(program) < p2.evaluate_r(program) {
-                    program[dst_addr] = 1;
-                } else {
-                    program[dst_addr] = 0;
-                }
-                pointer += instruction.instruction_size();
-            }
-            Instruction::Equals(p1, p2, p3) => {
-                let dst_addr = p3.evaluate_w(program) as usize;
-                if dst_addr >= program.len() {
-                    println!("Error at {:?} {:?}", pointer, program);
-                }
-                if p1.evaluate_r(program) == p2.evaluate_r(program) {
-                    program[dst_addr] = 1;
-                } else {
-                    program[dst_addr] = 0;
-                }
+            Instruction::AdjustBase(p1) => {
+                let offset = p1.evaluate_r(memory, relative_base).map_err(fault)?;
+                self.relative_base = self.overflow.add(relative_base, &offset).map_err(fault)?;
                 pointer += instruction.instruction_size();
             }
             Instruction::Halt => {
                 self.halt = true;
                 pointer += instruction.instruction_size();
             }
+            Instruction::Extension { .. } => pointer = self.execute_extension(instruction)?,
         }
-        pointer
+        Ok(pointer)
     }
 
-    fn parse_current_instruction(&self) -> Instruction {
-        let raw_opcode = self.program[self.pointer];
+    fn raw_opcode(&self) -> W {
+        self.memory
+            .cells
+            .get(self.pointer)
+            .cloned()
+            .unwrap_or_else(|| W::from_i64(0))
+    }
 
-        let (opcode, t1, t2, t3) = Self::parse_opcode(raw_opcode);
-        match opcode {
-            1 => Instruction::Add(
-                Param::from(self.program[self.pointer + 1], t1),
-                Param::from(self.program[self.pointer + 2], t2),
-                Param::from(self.program[self.pointer + 3], t2),
-            ),
-            2 => Instruction::Mul(
-                Param::from(self.program[self.pointer + 1], t1),
-                Param::from(self.program[self.pointer + 2], t2),
-                Param::from(self.program[self.pointer + 3], t2),
-            ),
-            3 => Instruction::Inp(Param::from(self.program[self.pointer + 1], t1)),
-            4 => Instruction::Out(Param::from(self.program[self.pointer + 1], t1)),
-            5 => Instruction::JumpTrue(
-                Param::from(self.program[self.pointer + 1], t1),
-                Param::from(self.program[self.pointer + 2], t2),
-            ),
-            6 => Instruction::JumpFalse(
-                Param::from(self.program[self.pointer + 1], t1),
-                Param::from(self.program[self.pointer + 2], t2),
-            ),
-            7 => Instruction::LessThan(
-                Param::from(self.program[self.pointer + 1], t1),
-                Param::from(self.program[self.pointer + 2], t2),
-                Param::from(self.program[self.pointer + 3], t3),
-            ),
-            8 => Instruction::Equals(
-                Param::from(self.program[self.pointer + 1], t1),
-                Param::from(self.program[self.pointer + 2], t2),
-                Param::from(self.program[self.pointer + 3], t3),
-            ),
-            99 => Instruction::Halt,
-            _ => unreachable!(),
+    /// Takes the instruction at the pointer out of the decode cache, or
+    /// decodes it. Hand it back with `cache_instruction` once it has run.
+    fn take_instruction(&mut self) -> Result<Instruction<W>, InterpreterError<W>> {
+        let cached = self
+            .memory
+            .decoded
+            .get_mut(self.pointer)
+            .and_then(Option::take);
+        let instruction = match cached {
+            Some(instruction) => instruction,
+            None => match self.decode_extension(self.pointer) {
+                Some(instruction) => instruction?,
+                None => Self::decode(&self.memory.cells, self.pointer, self.decoding, self.isa)?,
+            },
+        };
+        self.memory.taken = Some(self.pointer);
+        Ok(instruction)
+    }
+
+    /// Puts back an instruction taken from `pointer`, unless a write since
+    /// may have changed it.
+    fn cache_instruction(&mut self, pointer: usize, instruction: Instruction<W>) {
+        if self.memory.taken.take() != Some(pointer) {
+            return;
+        }
+        let decoded = &mut self.memory.decoded;
+        if decoded.len() <= pointer {
+            decoded.resize(self.memory.cells.len().max(pointer + 1), None);
         }
+        decoded[pointer] = Some(instruction);
+    }
+
+    /// Decodes the instruction stored at `pointer`, reading missing
+    /// parameters past the end of `memory` as 0.
+    fn decode(
+        memory: &[W],
+        pointer: usize,
+        decoding: DecodeMode,
+        isa: Isa,
+    ) -> Result<Instruction<W>, InterpreterError<W>> {
+        let params = ParamReader::new(memory, pointer, decoding, isa);
+        let (read, write) = (|index| params.read(index), |index| params.write(index));
+        let instruction = match params.code {
+            opcode if !isa.has_opcode(opcode) => {
+                return Err(InterpreterError::UnknownOpcode {
+                    pointer,
+                    opcode: params.opcode.clone(),
+                })
+            }
+            1 => Instruction::Add(read(1)?, read(2)?, write(3)?),
+            2 => Instruction::Mul(read(1)?, read(2)?, write(3)?),
+            3 => Instruction::Inp(write(1)?),
+            4 => Instruction::Out(read(1)?),
+            5 => Instruction::JumpTrue(read(1)?, read(2)?),
+            6 => Instruction::JumpFalse(read(1)?, read(2)?),
+            7 => Instruction::LessThan(read(1)?, read(2)?, write(3)?),
+            8 => Instruction::Equals(read(1)?, read(2)?, write(3)?),
+            9 => Instruction::AdjustBase(read(1)?),
+            99 => Instruction::Halt,
+            _ => {
+                return Err(InterpreterError::UnknownOpcode {
+                    pointer,
+                    opcode: params.opcode.clone(),
+                })
+            }
+        };
+        params.check_unused(instruction.params().len())?;
+        Ok(instruction)
     }
+}
 
+impl Interpreter {
     fn parse_opcode(raw_code: i64) -> (i64, i64, i64, i64) {
         let opcode = raw_code % 100;
         let params = raw_code / 100;
@@ -266,6 +1263,7 @@ mod test {
     use super::*;
 
     #[test]
+    #[allow(clippy::zero_prefixed_literal)]
     fn parse_opcode() {
         assert_eq!((2, 0, 1, 0), Interpreter::parse_opcode(1002));
         assert_eq!((1, 1, 1, 0), Interpreter::parse_opcode(1101));
@@ -278,10 +1276,432 @@ mod test {
         // This program checks if input is equal to 8
         let mut interpreter = Interpreter::new(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
         interpreter.add_input(1);
-        interpreter.execute_program();
+        interpreter.execute_program().unwrap();
         assert_eq!(
             0,
             *interpreter.get_output().last().expect("expected output")
         );
     }
+
+    #[test]
+    fn relative_base() {
+        // Moves the relative base to 8, stores the input there and outputs it back
+        let mut interpreter = Interpreter::new(vec![109, 8, 203, 0, 204, 0, 99, 0, 0]);
+        interpreter.add_input(42);
+        interpreter.execute_program().unwrap();
+        assert_eq!(vec![42], interpreter.get_output());
+
+        let mut interpreter = Interpreter::new(vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0]);
+        interpreter.execute_program().unwrap();
+        assert_eq!(vec![1219070632396864], interpreter.get_output());
+
+        let mut interpreter = Interpreter::new(vec![104, 1125899906842624, 99]);
+        interpreter.execute_program().unwrap();
+        assert_eq!(vec![1125899906842624], interpreter.get_output());
+    }
+
+    #[test]
+    fn growing_memory() {
+        // This program outputs a copy of itself, using memory past its end
+        let program = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let mut interpreter = Interpreter::new(program.clone());
+        interpreter.execute_program().unwrap();
+        assert_eq!(program, interpreter.get_output());
+    }
+
+    #[test]
+    fn self_modifying_code() {
+        // Outputs 7 and then increments the parameter of its own output
+        // instruction until it reaches 10
+        let mut interpreter = Interpreter::new(vec![
+            104, 7, 1001, 1, 1, 1, 1008, 1, 10, 14, 1006, 14, 0, 99, 0,
+        ]);
+        interpreter.execute_program().unwrap();
+        assert_eq!(vec![7, 8, 9], interpreter.get_output());
+
+        // Replaces its first instruction with a halt
+        let mut interpreter = Interpreter::new(vec![104, 7, 1101, 99, 0, 0, 1105, 1, 0]);
+        interpreter.execute_program().unwrap();
+        assert_eq!(vec![7], interpreter.get_output());
+
+        // Replaces itself with a halt, then jumps back to it
+        let mut interpreter =
+            Interpreter::new(vec![1101, 99, 0, 0, 1105, 1, 0]).with_instruction_limit(10);
+        assert_eq!(Ok(()), interpreter.execute_program());
+    }
+
+    #[test]
+    fn memory_limit() {
+        let mut interpreter = Interpreter::new(vec![1101, 1, 1, 1000, 99]).with_memory_limit(100);
+        assert_eq!(
+            Err(InterpreterError::AddressOutOfRange {
+                pointer: 0,
+                opcode: 1101,
+                address: 1000
+            }),
+            interpreter.execute_program()
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut interpreter = Interpreter::new(vec![1, 0, 0, 0, 42]);
+        assert_eq!(
+            Err(InterpreterError::UnknownOpcode {
+                pointer: 4,
+                opcode: 42
+            }),
+            interpreter.execute_program()
+        );
+
+        let mut interpreter = Interpreter::new(vec![304, 0, 99]);
+        assert_eq!(
+            Err(InterpreterError::InvalidMode {
+                pointer: 0,
+                opcode: 304,
+                param: 1,
+                mode: 3
+            }),
+            interpreter.execute_program()
+        );
+
+        let mut interpreter = Interpreter::new(vec![103, 0, 99]);
+        interpreter.add_input(1);
+        assert_eq!(
+            Err(InterpreterError::ImmediateWrite {
+                pointer: 0,
+                opcode: 103,
+                param: 1
+            }),
+            interpreter.execute_program()
+        );
+
+        let mut interpreter = Interpreter::new(vec![4, -1, 99]);
+        assert_eq!(
+            Err(InterpreterError::NegativeAddress {
+                pointer: 0,
+                opcode: 4,
+                address: -1
+            }),
+            interpreter.execute_program()
+        );
+
+        let mut interpreter = Interpreter::new(vec![4, 0, 3, 0, 99]);
+        assert_eq!(
+            Err(InterpreterError::InputExhausted {
+                pointer: 2,
+                opcode: 3
+            }),
+            interpreter.execute_program()
+        );
+        assert_eq!(vec![4], interpreter.get_output());
+    }
+
+    #[test]
+    fn decode_modes() {
+        // Adds 2 and 3 into cell 5, with the destination in immediate mode
+        let program = vec![11101, 2, 3, 5, 99, 0];
+        let mut interpreter = Interpreter::new(program.clone());
+        assert_eq!(
+            Err(InterpreterError::ImmediateWrite {
+                pointer: 0,
+                opcode: 11101,
+                param: 3
+            }),
+            interpreter.execute_program()
+        );
+        let mut interpreter = Interpreter::new(program).with_decode_mode(DecodeMode::Permissive);
+        interpreter.execute_program().unwrap();
+        assert_eq!(5, interpreter.memory.get(5));
+
+        // Outputs cell 0, with a mode digit for a third parameter
+        let program = vec![10004, 0, 99];
+        let mut interpreter = Interpreter::new(program.clone());
+        assert_eq!(
+            Err(InterpreterError::InvalidMode {
+                pointer: 0,
+                opcode: 10004,
+                param: 3,
+                mode: 1
+            }),
+            interpreter.execute_program()
+        );
+        let mut interpreter = Interpreter::new(program).with_decode_mode(DecodeMode::Permissive);
+        interpreter.execute_program().unwrap();
+        assert_eq!(vec![10004], interpreter.get_output());
+
+        // Unknown modes are rejected either way
+        let mut interpreter =
+            Interpreter::new(vec![7001, 0, 0, 0, 99]).with_decode_mode(DecodeMode::Permissive);
+        assert_eq!(
+            Err(InterpreterError::InvalidMode {
+                pointer: 0,
+                opcode: 7001,
+                param: 2,
+                mode: 7
+            }),
+            interpreter.execute_program()
+        );
+    }
+
+    #[test]
+    fn third_param_mode() {
+        // Add and Mul take the destination mode from the third mode digit,
+        // not the second
+        let mut interpreter = Interpreter::new(vec![1101, 2, 3, 5, 99, 0]);
+        interpreter.execute_program().unwrap();
+        assert_eq!(5, interpreter.memory.get(5));
+
+        // Moves the relative base to 4 and stores 109 * 4 at 4 + 3
+        let mut interpreter = Interpreter::new(vec![109, 4, 20002, 0, 1, 3, 99, 0]);
+        interpreter.execute_program().unwrap();
+        assert_eq!(0, interpreter.memory.get(3));
+        assert_eq!(436, interpreter.memory.get(7));
+    }
+
+    #[test]
+    fn isa_profiles() {
+        let run = |program: Vec<i64>, isa: Isa| {
+            let mut interpreter = Interpreter::new(program).with_isa(isa);
+            interpreter.add_input(7);
+            interpreter
+                .execute_program()
+                .map(|_| interpreter.get_output())
+        };
+        // Echoes its input
+        assert_eq!(Ok(vec![7]), run(vec![3, 5, 4, 5, 99, 0], Isa::Day5));
+        assert_eq!(
+            Err(InterpreterError::UnknownOpcode {
+                pointer: 0,
+                opcode: 3
+            }),
+            run(vec![3, 5, 4, 5, 99, 0], Isa::Day2)
+        );
+
+        // Echoes its input through the relative base
+        let program = vec![109, 5, 203, 2, 204, 2, 99, 0];
+        assert_eq!(Ok(vec![7]), run(program.clone(), Isa::Day9));
+        assert_eq!(
+            Err(InterpreterError::UnknownOpcode {
+                pointer: 0,
+                opcode: 109
+            }),
+            run(program, Isa::Day5)
+        );
+        assert_eq!(
+            Err(InterpreterError::InvalidMode {
+                pointer: 0,
+                opcode: 204,
+                param: 1,
+                mode: 2
+            }),
+            run(vec![204, 0, 99], Isa::Day5)
+        );
+        assert_eq!(
+            Err(InterpreterError::InvalidMode {
+                pointer: 0,
+                opcode: 1002,
+                param: 2,
+                mode: 1
+            }),
+            run(vec![1002, 4, 3, 4, 33], Isa::Day2)
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let run = |program: Vec<i64>, overflow: Overflow| {
+            let mut interpreter = Interpreter::new(program).with_overflow(overflow);
+            interpreter
+                .execute_program()
+                .map(|_| interpreter.get_output())
+        };
+        // Outputs i64::MAX * 2, then i64::MIN + -1
+        let product = vec![1002, 9, 2, 9, 4, 9, 99, 0, 0, i64::MAX];
+        let sum = vec![1101, i64::MIN, -1, 7, 4, 7, 99, 0];
+        assert_eq!(
+            Err(InterpreterError::Overflow {
+                pointer: 0,
+                opcode: 1002
+            }),
+            run(product.clone(), Overflow::Trap)
+        );
+        assert_eq!(Ok(vec![-2]), run(product.clone(), Overflow::Wrap));
+        assert_eq!(Ok(vec![i64::MAX]), run(product, Overflow::Saturate));
+        assert_eq!(Ok(vec![i64::MAX]), run(sum.clone(), Overflow::Wrap));
+        assert_eq!(Ok(vec![i64::MIN]), run(sum, Overflow::Saturate));
+
+        // Relative addresses always trap
+        let mut interpreter =
+            Interpreter::new(vec![109, i64::MAX, 204, 1, 99]).with_overflow(Overflow::Wrap);
+        assert_eq!(
+            Err(InterpreterError::Overflow {
+                pointer: 2,
+                opcode: 204
+            }),
+            interpreter.execute_program()
+        );
+    }
+
+    #[test]
+    fn run_states() {
+        // Echoes every input until it reads a 0
+        let mut interpreter = Interpreter::new(vec![3, 11, 4, 11, 1005, 11, 0, 99]);
+        assert_eq!(Ok(RunStatus::NeedsInput), interpreter.run());
+        assert_eq!(Ok(RunStatus::NeedsInput), interpreter.run());
+        interpreter.add_input(7);
+        assert_eq!(Ok(RunStatus::Output(7)), interpreter.run());
+        assert_eq!(Ok(RunStatus::NeedsInput), interpreter.run());
+        interpreter.add_input(0);
+        assert_eq!(Ok(RunStatus::Output(0)), interpreter.run());
+        assert_eq!(Ok(RunStatus::Halted), interpreter.run());
+        assert_eq!(Ok(RunStatus::Halted), interpreter.run());
+        assert!(interpreter.get_output().is_empty());
+
+        let mut interpreter = Interpreter::new(vec![3, 11, 4, 11, 1005, 11, 0, 99]);
+        interpreter.add_input(5);
+        assert_eq!(
+            Ok(RunStatus::LimitReached(Limit::Steps)),
+            interpreter.run_steps(1)
+        );
+        assert_eq!(Ok(RunStatus::Output(5)), interpreter.run_steps(1));
+    }
+
+    #[test]
+    fn limits() {
+        // Counts to 100 before outputting the count
+        let program = vec![
+            1001, 14, 1, 14, 1008, 14, 100, 15, 1006, 15, 0, 4, 14, 99, 0, 0,
+        ];
+        let mut interpreter = Interpreter::new(program.clone()).with_instruction_limit(50);
+        let mut stops = 0;
+        let output = loop {
+            match interpreter.run() {
+                Ok(RunStatus::LimitReached(Limit::Instructions)) => {
+                    assert_eq!(50 * (stops + 1), interpreter.instructions_executed());
+                    stops += 1;
+                    interpreter.raise_instruction_limit(50);
+                }
+                status => break status,
+            }
+        };
+        assert_eq!(Ok(RunStatus::Output(100)), output);
+        assert_eq!(6, stops);
+        assert_eq!(301, interpreter.instructions_executed());
+        assert_eq!(Ok(RunStatus::Halted), interpreter.run());
+        assert_eq!(Ok(RunStatus::Halted), interpreter.run());
+        // The halt counts once, as it does for execute_program
+        assert_eq!(302, interpreter.instructions_executed());
+        let mut whole = Interpreter::new(program.clone());
+        whole.execute_program().unwrap();
+        assert_eq!(302, whole.instructions_executed());
+
+        // Outputs 1 forever, the limit holds across calls to run
+        let mut interpreter = Interpreter::new(vec![104, 1, 1105, 1, 0]).with_instruction_limit(10);
+        let mut outputs = 0;
+        let status = loop {
+            match interpreter.run() {
+                Ok(RunStatus::Output(_)) => outputs += 1,
+                status => break status,
+            }
+        };
+        assert_eq!(Ok(RunStatus::LimitReached(Limit::Instructions)), status);
+        assert_eq!(5, outputs);
+        assert_eq!(10, interpreter.instructions_executed());
+        assert_eq!(
+            Ok(RunStatus::LimitReached(Limit::Instructions)),
+            interpreter.run()
+        );
+
+        // Jumps to itself forever
+        let mut interpreter = Interpreter::new(vec![1105, 1, 0]).with_instruction_limit(10);
+        assert_eq!(
+            Err(InterpreterError::LimitReached {
+                pointer: 0,
+                opcode: 1105,
+                limit: Limit::Instructions
+            }),
+            interpreter.execute_program()
+        );
+        assert_eq!(10, interpreter.instructions_executed());
+
+        let mut interpreter =
+            Interpreter::new(vec![1105, 1, 0]).with_time_limit(Duration::from_millis(10));
+        assert_eq!(Ok(RunStatus::LimitReached(Limit::Time)), interpreter.run());
+    }
+
+    #[test]
+    fn infinite_loops() {
+        // Counts to 100 and then toggles a flag forever
+        let program = vec![
+            1001, 20, 1, 20, 1008, 20, 100, 21, 1006, 21, 0, 1008, 22, 0, 22, 1105, 1, 11, 99, 0,
+            0, 0, 0,
+        ];
+        let mut interpreter = Interpreter::new(program.clone()).with_loop_detection();
+        assert_eq!(
+            Ok(RunStatus::InfiniteLoop {
+                entry: 11,
+                period: 4
+            }),
+            interpreter.run()
+        );
+        match interpreter.run() {
+            Ok(RunStatus::InfiniteLoop { entry: 11, .. }) => {}
+            status => panic!("expected the loop again, got {:?}", status),
+        }
+
+        let mut interpreter = Interpreter::new(program).with_loop_detection();
+        match interpreter.execute_program() {
+            Err(InterpreterError::InfiniteLoop { entry, period, .. }) => {
+                assert_eq!((11, 4), (entry, period))
+            }
+            result => panic!("expected an infinite loop, got {:?}", result),
+        }
+
+        // Reading input restarts the detection, so echoing programs don't loop
+        let mut interpreter = Interpreter::new(vec![3, 5, 1105, 1, 0, 0])
+            .with_loop_detection()
+            .with_instruction_limit(100);
+        for _ in 0..100 {
+            interpreter.add_input(7);
+        }
+        assert_eq!(
+            Ok(RunStatus::LimitReached(Limit::Instructions)),
+            interpreter.run()
+        );
+
+        // The counter above never repeats a state
+        let mut interpreter = Interpreter::new(vec![
+            1001, 20, 1, 20, 1008, 20, 100, 21, 1006, 21, 0, 4, 20, 99,
+        ])
+        .with_loop_detection();
+        assert_eq!(Ok(RunStatus::Output(100)), interpreter.run());
+
+        // Enters the loop at 6, which then runs through the lower address 3
+        let mut interpreter =
+            Interpreter::new(vec![1105, 1, 6, 1105, 1, 6, 1105, 1, 3]).with_loop_detection();
+        assert_eq!(
+            Ok(RunStatus::InfiniteLoop {
+                entry: 6,
+                period: 2
+            }),
+            interpreter.run()
+        );
+
+        // Writing output restarts the detection too, so printing forever isn't
+        // a loop
+        let mut interpreter = Interpreter::new(vec![104, 1, 1105, 1, 0])
+            .with_loop_detection()
+            .with_instruction_limit(100);
+        assert_eq!(
+            Err(InterpreterError::LimitReached {
+                pointer: 0,
+                opcode: 104,
+                limit: Limit::Instructions
+            }),
+            interpreter.execute_program()
+        );
+    }
 }
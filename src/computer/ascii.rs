@@ -0,0 +1,158 @@
+//! Helpers for programs that read and print text as ASCII codes, on
+//! `Interpreter<i64>` machines. Trailing values that aren't characters,
+//! usually the answer, are returned as i64.
+
+use super::{Interpreter, RunStatus};
+use std::char;
+use std::io::{self, BufRead, Write};
+
+/// Output of a text based program: the text it printed, plus any values
+/// after it that are not ASCII codes, usually the answer.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AsciiOutput {
+    pub text: String,
+    pub trailing: Vec<i64>,
+}
+
+impl AsciiOutput {
+    pub fn lines(&self) -> Vec<&str> {
+        self.text.lines().collect()
+    }
+}
+
+fn to_ascii(value: i64) -> Option<char> {
+    if (0..128).contains(&value) {
+        Some(value as u8 as char)
+    } else {
+        None
+    }
+}
+
+impl Interpreter {
+    /// Queues every byte of `text` as an input value.
+    pub fn add_ascii_input(&mut self, text: &str) {
+        for byte in text.bytes() {
+            self.add_input(i64::from(byte));
+        }
+    }
+
+    /// Drains the output queue as text. Non ASCII values in the middle of the
+    /// text show up as `char::REPLACEMENT_CHARACTER`.
+    pub fn take_ascii_output(&mut self) -> AsciiOutput {
+        let values: Vec<i64> = self.output.drain(..).collect();
+        let text_len = values
+            .iter()
+            .rposition(|value| to_ascii(*value).is_some())
+            .map_or(0, |last| last + 1);
+        AsciiOutput {
+            text: values[..text_len]
+                .iter()
+                .map(|value| to_ascii(*value).unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect(),
+            trailing: values[text_len..].to_vec(),
+        }
+    }
+
+    /// Plays a text based program: its output is printed to `output` and
+    /// each line read from `input` is sent to it when it asks for input.
+    /// Values that are not ASCII are printed as numbers on their own line.
+    ///
+    /// Returns `NeedsInput` if `input` ends while the program is waiting.
+    pub fn run_ascii_interactive<R: BufRead, W: Write>(
+        &mut self,
+        mut input: R,
+        mut output: W,
+    ) -> io::Result<RunStatus> {
+        loop {
+            let status = self.run().map_err(io::Error::other)?;
+            match status {
+                RunStatus::Output(value) => match to_ascii(value) {
+                    Some(c) => write!(output, "{}", c)?,
+                    None => writeln!(output, "{}", value)?,
+                },
+                RunStatus::NeedsInput => {
+                    output.flush()?;
+                    let mut line = String::new();
+                    if input.read_line(&mut line)? == 0 {
+                        return Ok(RunStatus::NeedsInput);
+                    }
+                    if !line.ends_with('\n') {
+                        line.push('\n');
+                    }
+                    self.add_ascii_input(&line);
+                }
+                status => {
+                    output.flush()?;
+                    return Ok(status);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::assembler::assemble;
+    use super::*;
+
+    // Greets the name typed on the first line and outputs its length times
+    // 1000, so that it can't be mistaken for text
+    const GREETER: &str = "
+                ARB #text
+        print:  JF @0, #ask
+                OUT @0
+                ARB #1
+                JT #1, #print
+        ask:    IN char
+                EQ char, #10, done
+                JT done, #finish
+                OUT char
+                ADD length, #1, length
+                JT #1, #ask
+        finish: OUT #33
+                OUT #10
+                MUL length, #1000, length
+                OUT length
+                HLT
+        char:   DATA 0
+        done:   DATA 0
+        length: DATA 0
+        text:   DATA 72, 105, 32, 0
+    ";
+
+    #[test]
+    fn ascii_input_and_output() {
+        let mut interpreter = Interpreter::new(assemble(GREETER).unwrap());
+        interpreter.add_ascii_input("Ada\n");
+        interpreter.execute_program().unwrap();
+        let output = interpreter.take_ascii_output();
+        assert_eq!("Hi Ada!\n", output.text);
+        assert_eq!(vec!["Hi Ada!"], output.lines());
+        assert_eq!(vec![3000], output.trailing);
+        assert!(interpreter.get_output().is_empty());
+
+        interpreter.output.extend(vec![104, 105, 10, 1000, 200]);
+        let output = interpreter.take_ascii_output();
+        assert_eq!("hi\n", output.text);
+        assert_eq!(vec![1000, 200], output.trailing);
+    }
+
+    #[test]
+    fn interactive() {
+        let mut interpreter = Interpreter::new(assemble(GREETER).unwrap());
+        let mut screen = Vec::new();
+        let status = interpreter
+            .run_ascii_interactive("Grace".as_bytes(), &mut screen)
+            .unwrap();
+        assert_eq!(RunStatus::Halted, status);
+        assert_eq!("Hi Grace!\n5000\n", String::from_utf8(screen).unwrap());
+
+        let mut interpreter = Interpreter::new(assemble(GREETER).unwrap());
+        let mut screen = Vec::new();
+        let status = interpreter
+            .run_ascii_interactive(io::empty(), &mut screen)
+            .unwrap();
+        assert_eq!(RunStatus::NeedsInput, status);
+        assert_eq!("Hi ", String::from_utf8(screen).unwrap());
+    }
+}
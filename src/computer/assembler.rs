@@ -0,0 +1,486 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+/// Mnemonic, opcode, parameter count and the index of the parameter the
+/// instruction writes to, if any.
+const OPCODES: [(&str, i64, usize, Option<usize>); 10] = [
+    ("ADD", 1, 3, Some(2)),
+    ("MUL", 2, 3, Some(2)),
+    ("IN", 3, 1, Some(0)),
+    ("OUT", 4, 1, None),
+    ("JT", 5, 2, None),
+    ("JF", 6, 2, None),
+    ("LT", 7, 3, Some(2)),
+    ("EQ", 8, 3, Some(2)),
+    ("ARB", 9, 1, None),
+    ("HLT", 99, 0, None),
+];
+
+/// Errors found while assembling, each tagged with its 1-based source line.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AssemblerError {
+    UnknownMnemonic {
+        line: usize,
+        mnemonic: String,
+    },
+    OperandCount {
+        line: usize,
+        mnemonic: String,
+        expected: usize,
+        found: usize,
+    },
+    InvalidOperand {
+        line: usize,
+        operand: String,
+    },
+    ImmediateWrite {
+        line: usize,
+        operand: String,
+    },
+    InvalidSymbol {
+        line: usize,
+        symbol: String,
+    },
+    DuplicateSymbol {
+        line: usize,
+        symbol: String,
+    },
+    UndefinedSymbol {
+        line: usize,
+        symbol: String,
+    },
+    /// The value of `operand` doesn't fit in an i64.
+    Overflow {
+        line: usize,
+        operand: String,
+    },
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssemblerError::UnknownMnemonic { line, mnemonic } => {
+                write!(f, "line {}: unknown mnemonic {}", line, mnemonic)
+            }
+            AssemblerError::OperandCount {
+                line,
+                mnemonic,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: {} takes {} operands, found {}",
+                line, mnemonic, expected, found
+            ),
+            AssemblerError::InvalidOperand { line, operand } => {
+                write!(f, "line {}: invalid operand {}", line, operand)
+            }
+            AssemblerError::ImmediateWrite { line, operand } => write!(
+                f,
+                "line {}: operand {} is written to and can't be immediate",
+                line, operand
+            ),
+            AssemblerError::InvalidSymbol { line, symbol } => {
+                write!(f, "line {}: invalid symbol name {}", line, symbol)
+            }
+            AssemblerError::DuplicateSymbol { line, symbol } => {
+                write!(f, "line {}: symbol {} is already defined", line, symbol)
+            }
+            AssemblerError::UndefinedSymbol { line, symbol } => {
+                write!(f, "line {}: undefined symbol {}", line, symbol)
+            }
+            AssemblerError::Overflow { line, operand } => {
+                write!(f, "line {}: operand {} overflows", line, operand)
+            }
+        }
+    }
+}
+
+impl error::Error for AssemblerError {}
+
+enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+/// A sum of signed terms, such as `loop`, `-3` or `buffer+2`.
+struct Expr {
+    text: String,
+    terms: Vec<(i64, Term)>,
+}
+
+impl Expr {
+    fn parse(text: &str, line: usize) -> Result<Self, AssemblerError> {
+        let invalid = || AssemblerError::InvalidOperand {
+            line,
+            operand: text.to_string(),
+        };
+        let mut terms = Vec::new();
+        let mut sign = 1;
+        let mut start = 0;
+        for (index, c) in text.char_indices().chain(Some((text.len(), '+'))) {
+            if c != '+' && c != '-' {
+                continue;
+            }
+            let token = text[start..index].trim();
+            start = index + 1;
+            if token.is_empty() {
+                // Only a leading sign, as in `-3`, may come without a term
+                if !terms.is_empty() || index == text.len() {
+                    return Err(invalid());
+                }
+                if c == '-' {
+                    sign = -sign;
+                }
+                continue;
+            }
+            terms.push((sign, Term::parse(token).ok_or_else(invalid)?));
+            sign = if c == '-' { -1 } else { 1 };
+        }
+        if terms.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Expr {
+            text: text.to_string(),
+            terms,
+        })
+    }
+
+    fn evaluate(&self, symbols: &HashMap<String, i64>, line: usize) -> Result<i64, AssemblerError> {
+        let mut value: i64 = 0;
+        for (sign, term) in &self.terms {
+            let term_value = match term {
+                Term::Number(number) => *number,
+                Term::Symbol(symbol) => {
+                    *symbols
+                        .get(symbol)
+                        .ok_or_else(|| AssemblerError::UndefinedSymbol {
+                            line,
+                            symbol: symbol.clone(),
+                        })?
+                }
+            };
+            value = term_value
+                .checked_mul(*sign)
+                .and_then(|term_value| value.checked_add(term_value))
+                .ok_or_else(|| AssemblerError::Overflow {
+                    line,
+                    operand: self.text.clone(),
+                })?;
+        }
+        Ok(value)
+    }
+}
+
+impl Term {
+    fn parse(text: &str) -> Option<Self> {
+        if let Ok(number) = text.parse() {
+            Some(Term::Number(number))
+        } else if is_symbol(text) {
+            Some(Term::Symbol(text.to_string()))
+        } else {
+            None
+        }
+    }
+}
+
+fn is_symbol(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Operands are plain for position mode, `#` prefixed for immediate mode and
+/// `@` prefixed for relative mode.
+struct Operand {
+    mode: i64,
+    expr: Expr,
+}
+
+impl Operand {
+    fn parse(text: &str, line: usize) -> Result<Self, AssemblerError> {
+        let (mode, rest) = if let Some(rest) = text.strip_prefix('#') {
+            (1, rest)
+        } else if let Some(rest) = text.strip_prefix('@') {
+            (2, rest)
+        } else {
+            (0, text)
+        };
+        Ok(Operand {
+            mode,
+            expr: Expr::parse(rest, line)?,
+        })
+    }
+}
+
+enum Item {
+    Instruction { opcode: i64, operands: Vec<Operand> },
+    Data(Vec<Expr>),
+}
+
+/// Assembles Intcode assembly into a program.
+///
+/// Each line holds an optional `label:`, then an instruction (`ADD a, #1, @2`),
+/// a `DATA` directive with a list of words, or a `CONST NAME value`
+/// definition. Everything after a `;` is a comment. Operands and data words
+/// can be numbers, labels, constants or sums of them like `buffer+2`.
+pub fn assemble(source: &str) -> Result<Vec<i64>, AssemblerError> {
+    let mut symbols = HashMap::new();
+    let mut constants = Vec::new();
+    let mut items = Vec::new();
+    let mut address = 0;
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut text = raw_line.split(';').next().unwrap_or("").trim();
+
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_symbol(label) {
+                return Err(AssemblerError::InvalidSymbol {
+                    line,
+                    symbol: label.to_string(),
+                });
+            }
+            define(&mut symbols, label, address as i64, line)?;
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = match text.find(char::is_whitespace) {
+            Some(split) => (&text[..split], text[split..].trim()),
+            None => (text, ""),
+        };
+        let mnemonic = mnemonic.to_uppercase();
+        let operands: Vec<&str> = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',').map(str::trim).collect()
+        };
+
+        match mnemonic.as_str() {
+            "CONST" => {
+                let mut parts = rest.splitn(2, char::is_whitespace);
+                let name = parts.next().unwrap_or("");
+                let value = parts.next().unwrap_or("").trim();
+                if !is_symbol(name) {
+                    return Err(AssemblerError::InvalidSymbol {
+                        line,
+                        symbol: name.to_string(),
+                    });
+                }
+                define(&mut symbols, name, 0, line)?;
+                constants.push((line, name.to_string(), Expr::parse(value, line)?));
+            }
+            "DATA" => {
+                let words = operands
+                    .iter()
+                    .map(|operand| Expr::parse(operand, line))
+                    .collect::<Result<Vec<_>, _>>()?;
+                address += words.len();
+                items.push((line, Item::Data(words)));
+            }
+            _ => {
+                let &(_, opcode, count, write) = OPCODES
+                    .iter()
+                    .find(|(name, _, _, _)| *name == mnemonic)
+                    .ok_or_else(|| AssemblerError::UnknownMnemonic {
+                        line,
+                        mnemonic: mnemonic.clone(),
+                    })?;
+                if operands.len() != count {
+                    return Err(AssemblerError::OperandCount {
+                        line,
+                        mnemonic,
+                        expected: count,
+                        found: operands.len(),
+                    });
+                }
+                let operands = operands
+                    .iter()
+                    .map(|operand| Operand::parse(operand, line))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if let Some(write) = write {
+                    if operands[write].mode == 1 {
+                        return Err(AssemblerError::ImmediateWrite {
+                            line,
+                            operand: rest.split(',').nth(write).unwrap_or("").trim().to_string(),
+                        });
+                    }
+                }
+                address += 1 + count;
+                items.push((line, Item::Instruction { opcode, operands }));
+            }
+        }
+    }
+
+    // Constants may use labels and the constants defined before them
+    for (_, name, _) in &constants {
+        symbols.remove(name);
+    }
+    for (line, name, expr) in constants {
+        let value = expr.evaluate(&symbols, line)?;
+        symbols.insert(name, value);
+    }
+
+    let mut program = Vec::with_capacity(address);
+    for (line, item) in items {
+        match item {
+            Item::Instruction { opcode, operands } => {
+                let modes = operands
+                    .iter()
+                    .rev()
+                    .fold(0, |modes, operand| modes * 10 + operand.mode);
+                program.push(modes * 100 + opcode);
+                for operand in operands {
+                    program.push(operand.expr.evaluate(&symbols, line)?);
+                }
+            }
+            Item::Data(words) => {
+                for word in words {
+                    program.push(word.evaluate(&symbols, line)?);
+                }
+            }
+        }
+    }
+    Ok(program)
+}
+
+fn define(
+    symbols: &mut HashMap<String, i64>,
+    symbol: &str,
+    value: i64,
+    line: usize,
+) -> Result<(), AssemblerError> {
+    if symbols.insert(symbol.to_string(), value).is_some() {
+        return Err(AssemblerError::DuplicateSymbol {
+            line,
+            symbol: symbol.to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::disassembler;
+    use super::super::{Interpreter, RunStatus};
+    use super::*;
+
+    #[test]
+    fn assemble_program() {
+        let source = "
+            ; Outputs the input number counting down to 1
+            CONST STEP -1
+                    IN counter
+            loop:   OUT counter
+                    ADD counter, #STEP, counter
+                    JT counter, #loop
+                    HLT
+            counter: DATA 0
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            vec![3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0],
+            program
+        );
+
+        let mut interpreter = Interpreter::new(program);
+        interpreter.add_input(3);
+        interpreter.execute_program().unwrap();
+        assert_eq!(vec![3, 2, 1], interpreter.get_output());
+    }
+
+    #[test]
+    fn relative_mode_and_expressions() {
+        let program =
+            assemble("ARB #buffer+1\nIN @-1\nOUT buffer\nHLT\nbuffer: DATA 0, buffer-1").unwrap();
+        assert_eq!(vec![109, 8, 203, -1, 4, 7, 99, 0, 6], program);
+
+        let mut interpreter = Interpreter::new(program);
+        interpreter.add_input(5);
+        assert_eq!(Ok(RunStatus::Output(5)), interpreter.run());
+    }
+
+    #[test]
+    fn disassembly_round_trip() {
+        let program = vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let source: Vec<String> = disassembler::disassemble(&program)
+            .into_iter()
+            .map(|line| line.text)
+            .collect();
+        assert_eq!(program, assemble(&source.join("\n")).unwrap());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Err(AssemblerError::UnknownMnemonic {
+                line: 2,
+                mnemonic: "JMP".to_string()
+            }),
+            assemble("HLT\njmp 0")
+        );
+        assert_eq!(
+            Err(AssemblerError::OperandCount {
+                line: 1,
+                mnemonic: "ADD".to_string(),
+                expected: 3,
+                found: 2
+            }),
+            assemble("ADD 1, 2")
+        );
+        assert_eq!(
+            Err(AssemblerError::ImmediateWrite {
+                line: 1,
+                operand: "#3".to_string()
+            }),
+            assemble("ADD 1, 2, #3")
+        );
+        assert_eq!(
+            Err(AssemblerError::InvalidOperand {
+                line: 1,
+                operand: "1x".to_string()
+            }),
+            assemble("OUT 1x")
+        );
+        assert_eq!(
+            Err(AssemblerError::DuplicateSymbol {
+                line: 3,
+                symbol: "a".to_string()
+            }),
+            assemble("a: HLT\n\na: HLT")
+        );
+        assert_eq!(
+            Err(AssemblerError::UndefinedSymbol {
+                line: 1,
+                symbol: "missing".to_string()
+            }),
+            assemble("OUT missing")
+        );
+        assert_eq!(
+            Err(AssemblerError::UndefinedSymbol {
+                line: 1,
+                symbol: "B".to_string()
+            }),
+            assemble("CONST A B\nCONST B 1")
+        );
+        assert_eq!(
+            Err(AssemblerError::Overflow {
+                line: 2,
+                operand: "9223372036854775807+1".to_string()
+            }),
+            assemble("HLT\nDATA 9223372036854775807+1")
+        );
+    }
+}
@@ -0,0 +1,314 @@
+use super::disassembler::{self, Line};
+use super::{DecodeMode, Instruction, Interpreter, Isa, Param};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::ops::Range;
+
+/// How control leaves a basic block.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Terminator {
+    /// Runs into the next block, which something else jumps to.
+    FallThrough,
+    /// A `JT` or `JF` that may or may not jump.
+    Branch,
+    /// A `JT` or `JF` whose condition is an immediate that always jumps.
+    Jump,
+    /// A `JT` or `JF` to an address only known at run time.
+    Indirect,
+    Halt,
+    /// A word that doesn't decode, or the end of the program.
+    Invalid,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EdgeKind {
+    FallThrough,
+    Taken,
+    NotTaken,
+}
+
+/// An edge between the blocks starting at `from` and `to`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// Instructions always executed one after the other, from `start` to the
+/// word before `end`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    pub end: usize,
+    pub lines: Vec<Line>,
+    pub terminator: Terminator,
+}
+
+/// The control flow graph of the code reachable from address 0. Code that
+/// modifies itself, or that is only reached through indirect jumps, isn't
+/// found.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<usize, Block>,
+    pub edges: Vec<Edge>,
+    /// Addresses of the jumps whose target isn't an immediate.
+    pub unresolved: Vec<usize>,
+    len: usize,
+}
+
+/// A decoded instruction and where it may go next. Instructions without a
+/// terminator go on to the next one.
+struct Node {
+    size: usize,
+    successors: Vec<(usize, EdgeKind)>,
+    terminator: Option<Terminator>,
+}
+
+fn node(program: &[i64], address: usize) -> Node {
+    let instruction = match Interpreter::decode(program, address, DecodeMode::Strict, Isa::Day9) {
+        Ok(instruction) if address + instruction.encoded_size() <= program.len() => instruction,
+        _ => {
+            return Node {
+                size: if address < program.len() { 1 } else { 0 },
+                successors: Vec::new(),
+                terminator: Some(Terminator::Invalid),
+            }
+        }
+    };
+    let size = instruction.encoded_size();
+    let next = address + size;
+    let straight = Node {
+        size,
+        successors: vec![(next, EdgeKind::FallThrough)],
+        terminator: None,
+    };
+    let (condition, target, jump_if) = match instruction {
+        Instruction::Halt => {
+            return Node {
+                size,
+                successors: Vec::new(),
+                terminator: Some(Terminator::Halt),
+            }
+        }
+        Instruction::JumpTrue(condition, target) => (condition, target, true),
+        Instruction::JumpFalse(condition, target) => (condition, target, false),
+        _ => return straight,
+    };
+    // Whether the jump is taken, when the condition is an immediate
+    let known = match condition {
+        Param::Value(value) => Some((value != 0) == jump_if),
+        _ => None,
+    };
+    if known == Some(false) {
+        return straight;
+    }
+
+    let target = match target {
+        Param::Value(target) if target >= 0 => Some(target as usize),
+        _ => None,
+    };
+    let mut successors = Vec::new();
+    if let Some(target) = target {
+        successors.push((target, EdgeKind::Taken));
+    }
+    if known.is_none() {
+        successors.push((next, EdgeKind::NotTaken));
+    }
+    let terminator = match (target, known) {
+        (None, _) => Terminator::Indirect,
+        (Some(_), Some(true)) => Terminator::Jump,
+        (Some(_), _) => Terminator::Branch,
+    };
+    Node {
+        size,
+        successors,
+        terminator: Some(terminator),
+    }
+}
+
+/// Builds the control flow graph of `program`, following every jump with an
+/// immediate target from address 0.
+pub fn build(program: &[i64]) -> Cfg {
+    let mut nodes = BTreeMap::new();
+    let mut leaders = BTreeSet::new();
+    leaders.insert(0);
+    let mut pending = vec![0];
+    while let Some(address) = pending.pop() {
+        if nodes.contains_key(&address) {
+            continue;
+        }
+        let node = node(program, address);
+        for (successor, _) in &node.successors {
+            if node.terminator.is_some() {
+                leaders.insert(*successor);
+            }
+            pending.push(*successor);
+        }
+        nodes.insert(address, node);
+    }
+
+    let mut cfg = Cfg {
+        blocks: BTreeMap::new(),
+        edges: Vec::new(),
+        unresolved: Vec::new(),
+        len: program.len(),
+    };
+    for &start in &leaders {
+        let mut address = start;
+        let mut count = 0;
+        let (terminator, successors) = loop {
+            let node = &nodes[&address];
+            let last = address;
+            address += node.size;
+            count += 1;
+            match node.terminator {
+                Some(terminator) => {
+                    if terminator == Terminator::Indirect {
+                        cfg.unresolved.push(last);
+                    }
+                    break (terminator, node.successors.clone());
+                }
+                None if leaders.contains(&address) => {
+                    break (Terminator::FallThrough, node.successors.clone())
+                }
+                None => {}
+            }
+        };
+        cfg.edges
+            .extend(successors.into_iter().map(|(to, kind)| Edge {
+                from: start,
+                to,
+                kind,
+            }));
+        cfg.blocks.insert(
+            start,
+            Block {
+                start,
+                end: address,
+                lines: disassembler::disassemble_from(program, start, count),
+                terminator,
+            },
+        );
+    }
+    cfg
+}
+
+impl Cfg {
+    /// Whether the word at `address` belongs to a reachable instruction.
+    pub fn is_code(&self, address: usize) -> bool {
+        self.blocks
+            .range(..=address)
+            .any(|(_, block)| address < block.end)
+    }
+
+    /// The ranges of words that no reachable instruction uses.
+    pub fn data(&self) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        for block in self.blocks.values() {
+            // Blocks past the end of the program are empty
+            let block_start = block.start.min(self.len);
+            if block_start > start {
+                ranges.push(start..block_start);
+            }
+            start = start.max(block.end);
+        }
+        if start < self.len {
+            ranges.push(start..self.len);
+        }
+        ranges
+    }
+
+    /// The graph in Graphviz DOT format, one box per block.
+    pub fn to_dot(&self) -> String {
+        let mut dot =
+            String::from("digraph intcode {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for block in self.blocks.values() {
+            let mut label: String = block
+                .lines
+                .iter()
+                .map(|line| format!("{:>5}: {}\\l", line.address, line.text))
+                .collect();
+            if block.lines.is_empty() {
+                label = format!("{:>5}: end of program\\l", block.start);
+            }
+            writeln!(dot, "    b{} [label=\"{}\"];", block.start, label).unwrap();
+        }
+        for edge in &self.edges {
+            let attributes = match edge.kind {
+                EdgeKind::FallThrough => "",
+                EdgeKind::Taken => " [label=\"taken\"]",
+                EdgeKind::NotTaken => " [label=\"not taken\", style=dashed]",
+            };
+            writeln!(dot, "    b{} -> b{}{};", edge.from, edge.to, attributes).unwrap();
+        }
+        for address in &self.unresolved {
+            let block = self.blocks.range(..=*address).next_back().unwrap().0;
+            writeln!(dot, "    u{} [label=\"?\", shape=circle];", address).unwrap();
+            writeln!(dot, "    b{} -> u{} [style=dotted];", block, address).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::fixtures::count_to_three;
+    use super::*;
+
+    #[test]
+    fn loop_blocks() {
+        let cfg = build(&count_to_three());
+        let blocks: Vec<(usize, usize, Terminator)> = cfg
+            .blocks
+            .values()
+            .map(|block| (block.start, block.end, block.terminator))
+            .collect();
+        assert_eq!(
+            vec![(0, 11, Terminator::Branch), (11, 14, Terminator::Halt)],
+            blocks
+        );
+        assert_eq!(
+            vec![
+                Edge {
+                    from: 0,
+                    to: 0,
+                    kind: EdgeKind::Taken
+                },
+                Edge {
+                    from: 0,
+                    to: 11,
+                    kind: EdgeKind::NotTaken
+                }
+            ],
+            cfg.edges
+        );
+        assert_eq!(vec![14..16], cfg.data());
+        assert!(cfg.is_code(12));
+        assert!(!cfg.is_code(14));
+    }
+
+    #[test]
+    fn jumps_and_dot() {
+        // Jumps over a data word to an indirect jump
+        let cfg = build(&[1105, 1, 4, 42, 6, 10, 11, 99, 0, 0, 0, 0]);
+        assert_eq!(vec![4], cfg.unresolved);
+        assert_eq!(vec![3..4, 8..12], cfg.data());
+        assert_eq!(
+            "digraph intcode {
+    node [shape=box, fontname=\"monospace\"];
+    b0 [label=\"    0: JT #1, #4\\l\"];
+    b4 [label=\"    4: JF 10, 11\\l\"];
+    b7 [label=\"    7: HLT\\l\"];
+    b0 -> b4 [label=\"taken\"];
+    b4 -> b7 [label=\"not taken\", style=dashed];
+    u4 [label=\"?\", shape=circle];
+    b4 -> u4 [style=dotted];
+}
+",
+            cfg.to_dot()
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use super::{Instruction, Interpreter, Memory, Word};
+
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Hash of one memory cell. The hash of the whole memory is the xor of its
+/// cells, so a write updates it in constant time. Cells holding 0 hash to 0,
+/// so growing the memory doesn't change it.
+pub(super) fn cell_hash<W: Word>(address: usize, value: &W) -> u64 {
+    match value.hash_bits() {
+        0 => 0,
+        bits => mix(mix(address as u64) ^ bits),
+    }
+}
+
+pub(super) fn memory_hash<W: Word>(cells: &[W]) -> u64 {
+    cells
+        .iter()
+        .enumerate()
+        .fold(0, |hash, (address, value)| hash ^ cell_hash(address, value))
+}
+
+/// Whether two memories are the same once missing cells are read as 0.
+fn same_cells<W: Word>(a: &[W], b: &[W]) -> bool {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    long[..short.len()] == *short && long[short.len()..].iter().all(Word::is_zero)
+}
+
+/// A machine state, with the hash of its memory to compare it quickly.
+pub(super) struct State<W> {
+    hash: u64,
+    pointer: usize,
+    relative_base: W,
+    cells: Vec<W>,
+}
+
+impl<W: Word> State<W> {
+    fn of(pointer: usize, relative_base: &W, memory: &Memory<W>) -> Self {
+        Self {
+            hash: memory.hash.unwrap_or_default(),
+            pointer,
+            relative_base: relative_base.clone(),
+            cells: memory.cells.clone(),
+        }
+    }
+
+    fn is(&self, pointer: usize, relative_base: &W, memory: &Memory<W>) -> bool {
+        self.hash == memory.hash.unwrap_or_default()
+            && self.pointer == pointer
+            && self.relative_base == *relative_base
+            && same_cells(&self.cells, &memory.cells)
+    }
+}
+
+/// A loop found by `LoopDetector`: the address of its first instruction,
+/// where the machine entered it, and how many instructions it takes to get
+/// back to the same state.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(super) struct Loop {
+    pub entry: usize,
+    pub period: u64,
+}
+
+/// Finds repeating machine states with Brent's algorithm: the state is saved
+/// after 1, 2, 4, 8... instructions and compared with every following one.
+/// Only the memory hash is compared until it matches, so most instructions
+/// cost a constant amount of work. The first state recorded is kept as well,
+/// to find where the loop starts once its period is known.
+pub(super) struct LoopDetector<W> {
+    start: Option<State<W>>,
+    /// The state compared with, once it is no longer `start`.
+    saved: Option<State<W>>,
+    power: u64,
+    steps: u64,
+    pub found: Option<Loop>,
+}
+
+impl<W: Word> LoopDetector<W> {
+    pub fn new() -> Self {
+        Self {
+            start: None,
+            saved: None,
+            power: 1,
+            steps: 0,
+            found: None,
+        }
+    }
+
+    /// Forgets the states seen so far, for when the machine consumes input
+    /// or produces output and its future may change.
+    pub fn reset(&mut self) {
+        self.start = None;
+        self.saved = None;
+        self.power = 1;
+        self.steps = 0;
+    }
+
+    /// Records the state the machine is in after an instruction. Returns the
+    /// state recorded first and the period once the machine is back in a
+    /// state it was in before.
+    pub fn record(
+        &mut self,
+        pointer: usize,
+        relative_base: &W,
+        memory: &Memory<W>,
+    ) -> Option<(State<W>, u64)> {
+        let compared = match (&self.saved, &self.start) {
+            (Some(saved), _) | (None, Some(saved)) => saved,
+            (None, None) => {
+                self.start = Some(State::of(pointer, relative_base, memory));
+                return None;
+            }
+        };
+        self.steps += 1;
+        if compared.is(pointer, relative_base, memory) {
+            // The search starts over from here, in case the machine goes on
+            let period = self.steps;
+            let state = State::of(pointer, relative_base, memory);
+            let start = self.start.replace(state).expect("a recorded state");
+            self.saved = None;
+            self.power = 1;
+            self.steps = 0;
+            return Some((start, period));
+        }
+        if self.steps == self.power {
+            self.saved = Some(State::of(pointer, relative_base, memory));
+            self.power *= 2;
+            self.steps = 0;
+        }
+        None
+    }
+}
+
+impl<W: Word> Interpreter<W> {
+    /// Records the state after `instruction` for loop detection. Instructions
+    /// that read input, write output or run an extension start the search
+    /// over from the state they leave.
+    pub(super) fn detect_loop(&mut self, instruction: &Instruction<W>) {
+        let detector = match &mut self.loop_detector {
+            Some(detector) => detector,
+            None => return,
+        };
+        match instruction {
+            Instruction::Inp(_) | Instruction::Out(_) | Instruction::Extension { .. } => {
+                detector.reset()
+            }
+            Instruction::Halt => return,
+            _ => {}
+        }
+        if let Some((start, period)) =
+            detector.record(self.pointer, &self.relative_base, &self.memory)
+        {
+            let entry = self.loop_entry(start, period);
+            if let Some(detector) = &mut self.loop_detector {
+                detector.found = Some(Loop { entry, period });
+            }
+        }
+    }
+
+    /// The address of the first instruction of the loop reached from `start`
+    /// that repeats every `period` instructions: Brent's second phase runs
+    /// two copies of the machine `period` instructions apart until they are
+    /// in the same state.
+    fn loop_entry(&self, start: State<W>, period: u64) -> usize {
+        let replay = |state: &State<W>| {
+            let mut machine = Interpreter::from_words(state.cells.clone())
+                .with_decode_mode(self.decoding)
+                .with_isa(self.isa)
+                .with_overflow(self.overflow);
+            machine.memory.limit = self.memory.limit;
+            machine.memory.hash = Some(state.hash);
+            machine.pointer = state.pointer;
+            machine.relative_base = state.relative_base.clone();
+            machine
+        };
+        let same = |a: &Self, b: &Self| {
+            a.memory.hash == b.memory.hash
+                && a.pointer == b.pointer
+                && a.relative_base == b.relative_base
+                && same_cells(&a.memory.cells, &b.memory.cells)
+        };
+        let (mut first, mut second) = (replay(&start), replay(&start));
+        // The loop ran without failing, so its replay doesn't either
+        for _ in 0..period {
+            if second.execute_step().is_err() {
+                return first.pointer;
+            }
+        }
+        while !same(&first, &second) {
+            if first.execute_step().is_err() || second.execute_step().is_err() {
+                break;
+            }
+        }
+        first.pointer
+    }
+}
@@ -0,0 +1,528 @@
+//! An interactive debugger for `Interpreter<i64>` machines. It parses and
+//! prints i64 values and lists code with the disassembler, which decodes i64
+//! programs.
+
+use super::disassembler;
+use super::{Access, Interpreter, InterpreterError, Limit, RunStatus};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+const HELP: &str = "\
+step [n]              (s) execute n instructions, 1 by default
+continue              (c) run until a breakpoint, watchpoint, halt or missing input
+back [n]              undo n instructions, 1 by default
+back write <addr>     undo up to the last instruction that wrote to addr
+break <addr>          (b) stop before executing the instruction at addr
+break op <opcode>     stop before executing any instruction with opcode
+watch <addr> [r|w|rw] (w) stop after addr is read, written or both (default rw)
+delete <addr>         remove the breakpoint and watchpoint at addr
+delete op <opcode>    remove an opcode breakpoint
+info                  (i) show registers, breakpoints, watchpoints and queues
+mem <addr> [count]    (x) show count memory cells starting at addr, at most 256
+set <addr> <value>    write value to memory, forgetting the history
+pointer <addr>        move the instruction pointer, forgetting the history
+base <value>          set the relative base, forgetting the history
+input <value>...      queue input values
+output [clear]        show the output queue, or clear it and the history
+list [addr] [count]   (l) disassemble from addr, the pointer by default
+quit                  (q) leave the debugger";
+
+/// Most cells shown by a single `mem` command.
+const MAX_MEM_CELLS: usize = 256;
+
+/// Which memory accesses trigger a watchpoint.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Watch {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Watch {
+    fn matches(self, access: &Access) -> bool {
+        matches!(
+            (self, access),
+            (Watch::ReadWrite, _)
+                | (Watch::Read, Access::Read { .. })
+                | (Watch::Write, Access::Write { .. })
+        )
+    }
+}
+
+/// Why the debugger stopped executing.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Stop {
+    Stepped,
+    Breakpoint(usize),
+    Opcode { pointer: usize, opcode: i64 },
+    Watchpoint(Access),
+    Halted,
+    NeedsInput,
+    LimitReached(Limit),
+    InfiniteLoop { entry: usize, period: u64 },
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Stepped => write!(f, "stepped"),
+            Stop::Breakpoint(address) => write!(f, "breakpoint at {}", address),
+            Stop::Opcode { pointer, opcode } => {
+                write!(f, "opcode {} breakpoint at {}", opcode, pointer)
+            }
+            Stop::Watchpoint(Access::Read { address, value }) => {
+                write!(f, "watchpoint: read {} from {}", value, address)
+            }
+            Stop::Watchpoint(Access::Write { address, old, new }) => {
+                write!(f, "watchpoint: wrote {} to {} (was {})", new, address, old)
+            }
+            Stop::Halted => write!(f, "halted"),
+            Stop::NeedsInput => write!(f, "waiting for input"),
+            Stop::LimitReached(limit) => write!(f, "{} limit reached", limit),
+            Stop::InfiniteLoop { entry, period } => write!(
+                f,
+                "infinite loop entered at {} repeating every {} instructions",
+                entry, period
+            ),
+        }
+    }
+}
+
+/// Steps an `Interpreter` under control of breakpoints and watchpoints.
+/// Commands that edit the machine directly clear its history, since `back`
+/// can't undo them.
+pub struct Debugger {
+    pub interpreter: Interpreter,
+    breakpoints: BTreeSet<usize>,
+    opcode_breakpoints: BTreeSet<i64>,
+    watchpoints: BTreeMap<usize, Watch>,
+}
+
+impl Debugger {
+    pub fn new(mut interpreter: Interpreter) -> Self {
+        interpreter.memory.log = Some(Vec::new());
+        Self {
+            interpreter,
+            breakpoints: BTreeSet::new(),
+            opcode_breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, address: usize) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn add_opcode_breakpoint(&mut self, opcode: i64) {
+        self.opcode_breakpoints.insert(opcode);
+    }
+
+    pub fn add_watchpoint(&mut self, address: usize, watch: Watch) {
+        self.watchpoints.insert(address, watch);
+    }
+
+    /// Removes the breakpoint and the watchpoint at `address`, returning
+    /// whether there was any.
+    pub fn remove(&mut self, address: usize) -> bool {
+        let breakpoint = self.breakpoints.remove(&address);
+        let watchpoint = self.watchpoints.remove(&address).is_some();
+        breakpoint || watchpoint
+    }
+
+    pub fn remove_opcode_breakpoint(&mut self, opcode: i64) -> bool {
+        self.opcode_breakpoints.remove(&opcode)
+    }
+
+    /// Executes up to `steps` instructions. Outputs are kept in the
+    /// interpreter's output queue.
+    pub fn step(&mut self, steps: usize) -> Result<Stop, InterpreterError> {
+        self.execute(Some(steps))
+    }
+
+    /// Executes until a breakpoint or watchpoint triggers, the program halts
+    /// or it waits for input.
+    pub fn resume(&mut self) -> Result<Stop, InterpreterError> {
+        self.execute(None)
+    }
+
+    fn execute(&mut self, steps: Option<usize>) -> Result<Stop, InterpreterError> {
+        // One budget for all the steps, so time limits still fire
+        let budget = self.interpreter.budget();
+        let mut executed = 0;
+        loop {
+            if Some(executed) == steps {
+                return Ok(Stop::Stepped);
+            }
+            // Breakpoints at the current instruction don't fire, so that
+            // execution can resume from them.
+            if executed > 0 {
+                let pointer = self.interpreter.pointer;
+                let opcode = self.interpreter.raw_opcode() % 100;
+                if self.breakpoints.contains(&pointer) {
+                    return Ok(Stop::Breakpoint(pointer));
+                }
+                if self.opcode_breakpoints.contains(&opcode) {
+                    return Ok(Stop::Opcode { pointer, opcode });
+                }
+            }
+            match self.interpreter.run_steps_within(&budget, 1)? {
+                RunStatus::Halted => return Ok(Stop::Halted),
+                RunStatus::NeedsInput => return Ok(Stop::NeedsInput),
+                RunStatus::Output(value) => self.interpreter.requeue_output(value),
+                RunStatus::LimitReached(Limit::Steps) => {}
+                RunStatus::LimitReached(limit) => return Ok(Stop::LimitReached(limit)),
+                RunStatus::InfiniteLoop { entry, period } => {
+                    return Ok(Stop::InfiniteLoop { entry, period })
+                }
+            }
+            executed += 1;
+            if let Some(access) = self.triggered_watchpoint() {
+                return Ok(Stop::Watchpoint(access));
+            }
+        }
+    }
+
+    fn triggered_watchpoint(&self) -> Option<Access> {
+        let log = self.interpreter.memory.log.as_ref()?;
+        log.iter().cloned().find(|access| {
+            self.watchpoints
+                .get(&access.address())
+                .is_some_and(|watch| watch.matches(access))
+        })
+    }
+
+    /// Runs one debugger command, returning the text to show the user.
+    pub fn command(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Ok(String::new()),
+        };
+        match (command, args) {
+            ("help", _) | ("h", _) => Ok(HELP.to_string()),
+            ("step", _) | ("s", _) => {
+                let steps = match args.first() {
+                    Some(steps) => parse(steps)?,
+                    None => 1,
+                };
+                let stop = self.step(steps).map_err(|e| e.to_string())?;
+                Ok(self.describe(stop))
+            }
+            ("continue", []) | ("c", []) => {
+                let stop = self.resume().map_err(|e| e.to_string())?;
+                Ok(self.describe(stop))
+            }
+            ("back", ["write", address]) => {
+                let address: usize = parse(address)?;
+                if self.interpreter.run_back_to_write(address) {
+                    Ok(self.current_instruction())
+                } else {
+                    Err(format!("no write to {} in the history", address))
+                }
+            }
+            ("back", _) => {
+                let steps = match args.first() {
+                    Some(steps) => parse(steps)?,
+                    None => 1,
+                };
+                let undone = (0..steps)
+                    .take_while(|_| self.interpreter.step_back())
+                    .count();
+                if undone == 0 {
+                    return Err("no history to go back through".to_string());
+                }
+                Ok(format!(
+                    "went back {} instructions\n{}",
+                    undone,
+                    self.current_instruction()
+                ))
+            }
+            ("break", ["op", opcode]) | ("b", ["op", opcode]) => {
+                self.add_opcode_breakpoint(parse(opcode)?);
+                Ok(format!("breakpoint on opcode {}", opcode))
+            }
+            ("break", [address]) | ("b", [address]) => {
+                self.add_breakpoint(parse(address)?);
+                Ok(format!("breakpoint at {}", address))
+            }
+            ("watch", [address, kind @ ..]) | ("w", [address, kind @ ..]) => {
+                let watch = match kind {
+                    [] | ["rw"] => Watch::ReadWrite,
+                    ["r"] => Watch::Read,
+                    ["w"] => Watch::Write,
+                    _ => return Err(format!("unknown watch kind {}", kind.join(" "))),
+                };
+                self.add_watchpoint(parse(address)?, watch);
+                Ok(format!("watchpoint at {}", address))
+            }
+            ("delete", ["op", opcode]) => {
+                if self.remove_opcode_breakpoint(parse(opcode)?) {
+                    Ok(format!("removed breakpoint on opcode {}", opcode))
+                } else {
+                    Err(format!("no breakpoint on opcode {}", opcode))
+                }
+            }
+            ("delete", [address]) => {
+                if self.remove(parse(address)?) {
+                    Ok(format!("removed breakpoints at {}", address))
+                } else {
+                    Err(format!("no breakpoint at {}", address))
+                }
+            }
+            ("info", []) | ("i", []) => Ok(self.info()),
+            ("mem", [address, count @ ..]) | ("x", [address, count @ ..]) => {
+                let address: usize = parse(address)?;
+                let count = match count {
+                    [] => 1,
+                    [count] => parse(count)?,
+                    _ => return Err("usage: mem <addr> [count]".to_string()),
+                };
+                let size = self.interpreter.memory.cells.len();
+                if address >= size {
+                    return Err(format!("address {} is past the end of memory", address));
+                }
+                let end = address.saturating_add(count.min(MAX_MEM_CELLS)).min(size);
+                let cells: Vec<String> = self.interpreter.memory.cells[address..end]
+                    .iter()
+                    .zip(address..)
+                    .map(|(value, cell)| format!("{:>5}: {}", cell, value))
+                    .collect();
+                Ok(cells.join("\n"))
+            }
+            ("set", [address, value]) => {
+                let address: usize = parse(address)?;
+                let value = parse(value)?;
+                self.write(address, value)?;
+                self.interpreter.clear_history();
+                Ok(format!("{:>5}: {}", address, value))
+            }
+            ("pointer", [address]) => {
+                self.interpreter.pointer = parse(address)?;
+                self.interpreter.clear_history();
+                Ok(self.current_instruction())
+            }
+            ("base", [value]) => {
+                self.interpreter.relative_base = parse(value)?;
+                self.interpreter.clear_history();
+                Ok(format!("relative base {}", self.interpreter.relative_base))
+            }
+            ("input", values) if !values.is_empty() => {
+                for value in values {
+                    self.interpreter.add_input(parse(value)?);
+                }
+                Ok(format!("input {:?}", self.interpreter.input))
+            }
+            ("output", []) => Ok(format!("output {:?}", self.interpreter.output)),
+            ("output", ["clear"]) => {
+                self.interpreter.output.clear();
+                self.interpreter.clear_history();
+                Ok("output cleared".to_string())
+            }
+            ("list", _) | ("l", _) => {
+                let address = match args.first() {
+                    Some(address) => parse(address)?,
+                    None => self.interpreter.pointer,
+                };
+                let count = match args.get(1) {
+                    Some(count) => parse(count)?,
+                    None => 10,
+                };
+                let lines: Vec<String> =
+                    disassembler::disassemble_from(&self.interpreter.memory.cells, address, count)
+                        .iter()
+                        .map(|line| self.mark(line))
+                        .collect();
+                Ok(lines.join("\n"))
+            }
+            _ => Err(format!("unknown command {}, try help", line.trim())),
+        }
+    }
+
+    fn write(&mut self, address: usize, value: i64) -> Result<(), String> {
+        let memory = &mut self.interpreter.memory;
+        let log = memory.log.take();
+        let written = memory.write(address as i64, value);
+        memory.log = log;
+        written.map_err(|_| format!("address {} exceeds the memory limit", address))
+    }
+
+    fn describe(&self, stop: Stop) -> String {
+        format!("{}\n{}", stop, self.current_instruction())
+    }
+
+    fn current_instruction(&self) -> String {
+        let pointer = self.interpreter.pointer;
+        disassembler::disassemble_from(&self.interpreter.memory.cells, pointer, 1)
+            .first()
+            .map(|line| self.mark(line))
+            .unwrap_or_else(|| format!("=> {:>5}: end of memory", pointer))
+    }
+
+    fn mark(&self, line: &disassembler::Line) -> String {
+        let marker = if line.address == self.interpreter.pointer {
+            "=>"
+        } else if self.breakpoints.contains(&line.address) {
+            " *"
+        } else {
+            "  "
+        };
+        format!("{} {}", marker, line)
+    }
+
+    fn info(&self) -> String {
+        format!(
+            "pointer {}\nrelative base {}\nbreakpoints {:?}\nopcode breakpoints {:?}\nwatchpoints {:?}\ninput {:?}\noutput {:?}",
+            self.interpreter.pointer,
+            self.interpreter.relative_base,
+            self.breakpoints,
+            self.opcode_breakpoints,
+            self.watchpoints,
+            self.interpreter.input,
+            self.interpreter.output
+        )
+    }
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse().map_err(|_| format!("invalid number {}", text))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Sums every input until it reads a 0, then outputs the total
+    fn sum_until_zero() -> Vec<i64> {
+        vec![3, 13, 1, 13, 14, 14, 1005, 13, 0, 4, 14, 99, 0, 0, 0]
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut debugger = Debugger::new(Interpreter::new(sum_until_zero()));
+        debugger.add_breakpoint(6);
+        debugger.interpreter.add_input(3);
+        debugger.interpreter.add_input(0);
+        assert_eq!(Ok(Stop::Breakpoint(6)), debugger.resume());
+        assert_eq!(Ok(Stop::Breakpoint(6)), debugger.resume());
+        debugger.remove(6);
+        debugger.add_opcode_breakpoint(4);
+        assert_eq!(
+            Ok(Stop::Opcode {
+                pointer: 9,
+                opcode: 4
+            }),
+            debugger.resume()
+        );
+        assert_eq!(Ok(Stop::Halted), debugger.resume());
+        assert_eq!(vec![3], debugger.interpreter.get_output());
+    }
+
+    #[test]
+    fn watchpoints_and_steps() {
+        let mut debugger = Debugger::new(Interpreter::new(sum_until_zero()));
+        assert_eq!(Ok(Stop::NeedsInput), debugger.resume());
+        debugger.interpreter.add_input(5);
+        debugger.interpreter.add_input(0);
+        debugger.add_watchpoint(14, Watch::Write);
+        assert_eq!(
+            Ok(Stop::Watchpoint(Access::Write {
+                address: 14,
+                old: 0,
+                new: 5
+            })),
+            debugger.resume()
+        );
+        assert_eq!(Ok(Stop::Stepped), debugger.step(2));
+        assert_eq!(2, debugger.interpreter.pointer);
+        debugger.add_watchpoint(14, Watch::Read);
+        assert_eq!(
+            Ok(Stop::Watchpoint(Access::Read {
+                address: 14,
+                value: 5
+            })),
+            debugger.step(10)
+        );
+    }
+
+    #[test]
+    fn commands() {
+        let mut debugger = Debugger::new(Interpreter::new(sum_until_zero()));
+        assert!(debugger.command("help").unwrap().contains("watch"));
+        assert_eq!(Ok("breakpoint at 9".to_string()), debugger.command("b 9"));
+        debugger.command("input 4 0").unwrap();
+        assert_eq!(
+            "breakpoint at 9\n=>     9: OUT 14                       ; 4,14",
+            debugger.command("c").unwrap()
+        );
+        assert_eq!(Ok("   14: 4".to_string()), debugger.command("x 14"));
+        debugger.command("set 14 40").unwrap();
+        debugger.command("s").unwrap();
+        assert_eq!(Ok("output [40]".to_string()), debugger.command("output"));
+        assert!(debugger.command("pointer x").is_err());
+        assert!(debugger.command("jump 3").is_err());
+
+        let cells = debugger.command(&format!("x 10 {}", usize::MAX)).unwrap();
+        assert_eq!(5, cells.lines().count());
+        assert!(debugger.command("x 15").is_err());
+        let mut debugger = Debugger::new(Interpreter::new(vec![0; 1000]));
+        let cells = debugger.command("x 0 1000").unwrap();
+        assert_eq!(MAX_MEM_CELLS, cells.lines().count());
+    }
+
+    #[test]
+    fn endless_loops() {
+        // Jumps back to itself forever
+        let mut debugger = Debugger::new(Interpreter::new(vec![1105, 1, 0]).with_loop_detection());
+        assert_eq!(
+            Ok(Stop::InfiniteLoop {
+                entry: 0,
+                period: 1
+            }),
+            debugger.resume()
+        );
+        assert_eq!(
+            "infinite loop entered at 0 repeating every 1 instructions",
+            debugger.resume().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn time_limit() {
+        let interpreter = Interpreter::new(vec![1105, 1, 0])
+            .with_time_limit(std::time::Duration::from_millis(10));
+        let mut debugger = Debugger::new(interpreter);
+        assert_eq!(Ok(Stop::LimitReached(Limit::Time)), debugger.resume());
+    }
+
+    #[test]
+    fn going_back() {
+        let mut debugger = Debugger::new(Interpreter::new(sum_until_zero()).with_history(100));
+        debugger.command("input 4 6 0").unwrap();
+        assert_eq!(Ok(Stop::Halted), debugger.resume());
+        assert_eq!(Ok("output [10]".to_string()), debugger.command("output"));
+
+        // The halt is undone first, then the output
+        debugger.command("back").unwrap();
+        assert_eq!(Ok("output [10]".to_string()), debugger.command("output"));
+        debugger.command("back").unwrap();
+        assert_eq!(Ok("output []".to_string()), debugger.command("output"));
+        assert_eq!(
+            Ok("=>     2: ADD 13, 14, 14               ; 1,13,14,14".to_string()),
+            debugger.command("back write 14")
+        );
+        assert_eq!(Ok("   14: 10".to_string()), debugger.command("x 14"));
+        assert!(debugger
+            .command("back 100")
+            .unwrap()
+            .starts_with("went back 7 instructions"));
+        assert!(debugger.command("back").is_err());
+        assert_eq!(Ok("output []".to_string()), debugger.command("output"));
+
+        debugger.command("step 3").unwrap();
+        debugger.command("set 14 1").unwrap();
+        assert!(debugger.command("back").is_err());
+        debugger.command("step").unwrap();
+        debugger.command("pointer 0").unwrap();
+        assert!(debugger.command("back").is_err());
+    }
+}
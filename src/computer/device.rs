@@ -0,0 +1,166 @@
+use super::{Interpreter, RunStatus, Word};
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, Sender};
+
+/// Where an `Interpreter` reads input from once its input queue is empty.
+pub trait InputSource<W = i64>: Send {
+    /// The next input value, or `None` if there is none to give.
+    fn read(&mut self) -> Option<W>;
+}
+
+/// Where an `Interpreter` writes its output values.
+pub trait OutputSink<W = i64>: Send {
+    fn write(&mut self, value: W);
+}
+
+impl<W: Send> InputSource<W> for VecDeque<W> {
+    fn read(&mut self) -> Option<W> {
+        self.pop_front()
+    }
+}
+
+impl<W: Send> OutputSink<W> for VecDeque<W> {
+    fn write(&mut self, value: W) {
+        self.push_back(value);
+    }
+}
+
+impl<W: Send> OutputSink<W> for Vec<W> {
+    fn write(&mut self, value: W) {
+        self.push(value);
+    }
+}
+
+impl<W, F: FnMut() -> Option<W> + Send> InputSource<W> for F {
+    fn read(&mut self) -> Option<W> {
+        self()
+    }
+}
+
+impl<W, F: FnMut(W) + Send> OutputSink<W> for F {
+    fn write(&mut self, value: W) {
+        self(value)
+    }
+}
+
+/// Blocks until a value arrives, or gives `None` once every sender is gone.
+impl<W: Send> InputSource<W> for Receiver<W> {
+    fn read(&mut self) -> Option<W> {
+        self.recv().ok()
+    }
+}
+
+/// Values sent after the receiver is gone are dropped.
+impl<W: Send> OutputSink<W> for Sender<W> {
+    fn write(&mut self, value: W) {
+        let _ = self.send(value);
+    }
+}
+
+/// Runs the machine until its next output. It gives `None` once it halts,
+/// waits for input or fails.
+impl<W: Word> InputSource<W> for Interpreter<W> {
+    fn read(&mut self) -> Option<W> {
+        match self.run() {
+            Ok(RunStatus::Output(value)) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Queues the value as input and runs the machine until it halts, waits for
+/// more input or fails. Its own outputs go to its output device, or stay in
+/// its output queue.
+impl<W: Word> OutputSink<W> for Interpreter<W> {
+    fn write(&mut self, value: W) {
+        self.add_input(value);
+        while let Ok(RunStatus::Output(value)) = self.run() {
+            self.requeue_output(value);
+        }
+    }
+}
+
+/// Feeds the values of an iterator as input.
+pub struct IterInput<I>(I);
+
+pub fn iter_input<I: IntoIterator>(values: I) -> IterInput<I::IntoIter> {
+    IterInput(values.into_iter())
+}
+
+impl<I: Iterator + Send> InputSource<I::Item> for IterInput<I> {
+    fn read(&mut self) -> Option<I::Item> {
+        self.0.next()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+
+    // Outputs every input doubled until it reads a 0
+    fn double_until_zero() -> Vec<i64> {
+        vec![
+            3, 15, 1006, 15, 14, 1002, 15, 2, 16, 4, 16, 1105, 1, 0, 99, 0, 0,
+        ]
+    }
+
+    #[test]
+    fn closures_and_iterators() {
+        let outputs = Arc::new(Mutex::new(Vec::new()));
+        let sink = outputs.clone();
+        let mut counter = 0;
+        let mut interpreter = Interpreter::new(double_until_zero())
+            .with_input(move || {
+                counter += 1;
+                if counter < 4 {
+                    Some(counter)
+                } else {
+                    None
+                }
+            })
+            .with_output(move |value| sink.lock().unwrap().push(value));
+        assert_eq!(Ok(RunStatus::NeedsInput), interpreter.run());
+        assert_eq!(vec![2, 4, 6], *outputs.lock().unwrap());
+
+        let mut interpreter =
+            Interpreter::new(double_until_zero()).with_input(iter_input(vec![5, 0]));
+        interpreter.execute_program().unwrap();
+        assert_eq!(vec![10], interpreter.get_output());
+    }
+
+    #[test]
+    fn channels() {
+        let (input, receiver) = mpsc::channel();
+        let (sender, output) = mpsc::channel();
+        let mut interpreter = Interpreter::new(double_until_zero())
+            .with_input(receiver)
+            .with_output(sender);
+        input.send(21).unwrap();
+        input.send(0).unwrap();
+        interpreter.execute_program().unwrap();
+        assert_eq!(vec![42], output.try_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn chained_machines() {
+        let mut first = Interpreter::new(double_until_zero());
+        first.add_input(1);
+        first.add_input(3);
+        first.add_input(0);
+        let mut second = Interpreter::new(double_until_zero()).with_input(first);
+        second.execute_program().unwrap_err();
+        assert_eq!(vec![4, 12], second.get_output());
+
+        // The other way around, the first machine pushes into the second
+        let (sender, output) = mpsc::channel();
+        let second = Interpreter::new(double_until_zero()).with_output(sender);
+        let mut first = Interpreter::new(double_until_zero()).with_output(second);
+        for input in &[1, 3, 0] {
+            first.add_input(*input);
+        }
+        first.execute_program().unwrap();
+        assert_eq!(vec![4, 12], output.try_iter().collect::<Vec<_>>());
+    }
+}
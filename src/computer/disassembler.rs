@@ -0,0 +1,120 @@
+use super::{DecodeMode, Instruction, Interpreter, Isa, Param, Word};
+use std::fmt;
+
+/// One line of a disassembly listing: an instruction, or a single data word
+/// that could not be decoded.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: usize,
+    pub words: Vec<i64>,
+    pub text: String,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words: Vec<String> = self.words.iter().map(|word| word.to_string()).collect();
+        write!(
+            f,
+            "{:>5}: {:<28} ; {}",
+            self.address,
+            self.text,
+            words.join(",")
+        )
+    }
+}
+
+impl<W: Word> fmt::Display for Param<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Param::Position(address) => write!(f, "{}", address),
+            Param::Value(value) => write!(f, "#{}", value),
+            Param::Relative(offset) => write!(f, "@{}", offset),
+        }
+    }
+}
+
+impl<W: Word> fmt::Display for Instruction<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = self.params().iter().map(|p| p.to_string()).collect();
+        if params.is_empty() {
+            write!(f, "{}", self.mnemonic())
+        } else {
+            write!(f, "{} {}", self.mnemonic(), params.join(", "))
+        }
+    }
+}
+
+/// Decodes `program` from address 0 onwards. Words that are not a valid
+/// instruction, or whose parameters run past the end of the program, are
+/// emitted one at a time as `DATA`.
+pub fn disassemble(program: &[i64]) -> Vec<Line> {
+    disassemble_from(program, 0, program.len())
+}
+
+/// Decodes at most `count` lines of `program` starting at `address`.
+pub fn disassemble_from(program: &[i64], mut address: usize, count: usize) -> Vec<Line> {
+    let mut lines = Vec::new();
+    while address < program.len() && lines.len() < count {
+        let (size, text) = decode_line(program, address);
+        lines.push(Line {
+            address,
+            words: program[address..address + size].to_vec(),
+            text,
+        });
+        address += size;
+    }
+    lines
+}
+
+/// The size and text of the line at `address`, which must be in `program`.
+pub(super) fn decode_line<W: Word>(program: &[W], address: usize) -> (usize, String) {
+    match Interpreter::decode(program, address, DecodeMode::Strict, Isa::Day9) {
+        Ok(ref instruction) if address + instruction.encoded_size() <= program.len() => {
+            (instruction.encoded_size(), instruction.to_string())
+        }
+        _ => (1, format!("DATA {}", program[address])),
+    }
+}
+
+/// The annotated listing of `program`, one line per instruction.
+pub fn listing(program: &[i64]) -> String {
+    disassemble(program)
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn disassemble_program() {
+        let lines = disassemble(&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+        let text: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(
+            vec!["IN 9", "EQ 9, 10, 9", "OUT 9", "HLT", "DATA -1", "DATA 8"],
+            text
+        );
+        assert_eq!(vec![8, 9, 10, 9], lines[1].words);
+        assert_eq!(2, lines[1].address);
+    }
+
+    #[test]
+    fn parameter_modes() {
+        let lines = disassemble(&[1201, -2, 7, 3, 109, 19, 1105, 1, 0, 204, 5]);
+        let text: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(
+            vec!["ADD @-2, #7, 3", "ARB #19", "JT #1, #0", "OUT @5"],
+            text
+        );
+    }
+
+    #[test]
+    fn listing_format() {
+        assert_eq!(
+            "    0: MUL 4, #3, 4                 ; 1002,4,3,4\n    4: HLT                          ; 99\n",
+            listing(&[1002, 4, 3, 4, 99])
+        );
+    }
+}
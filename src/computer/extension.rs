@@ -0,0 +1,401 @@
+use super::{
+    next_input, send_output, to_address, DecodeMode, Fault, InputSource, Instruction, Interpreter,
+    InterpreterError, Isa, Memory, OutputSink, ParamReader, Word, MAX_INSTRUCTION_SIZE,
+};
+use std::collections::VecDeque;
+
+/// How an extension instruction uses one of its parameters.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Role {
+    /// The handler gets the parameter's value.
+    Read,
+    /// The handler gets the address the parameter points to. Immediate mode
+    /// is rejected like for the built-in instructions.
+    Write,
+}
+
+type Handler<W> = Box<dyn FnMut(&mut Machine<W>, &[W]) -> Result<(), InterpreterError<W>> + Send>;
+
+/// An opcode registered with `Interpreter::with_extension`.
+pub(super) struct Extension<W> {
+    roles: Vec<Role>,
+    handler: Handler<W>,
+}
+
+/// The machine state an extension handler may use while its instruction
+/// runs.
+pub struct Machine<'a, W = i64> {
+    memory: &'a mut Memory<W>,
+    input: &'a mut VecDeque<W>,
+    input_device: &'a mut Option<Box<dyn InputSource<W>>>,
+    output: &'a mut VecDeque<W>,
+    output_device: &'a mut Option<Box<dyn OutputSink<W>>>,
+    relative_base: &'a mut W,
+    halt: &'a mut bool,
+    pointer: usize,
+    opcode: W,
+    next: usize,
+    /// Every cell written, with its previous value, to undo them if the
+    /// handler runs out of input.
+    writes: Vec<(usize, W)>,
+    inputs: Vec<W>,
+    queued_outputs: usize,
+}
+
+impl<'a, W: Word> Machine<'a, W> {
+    /// Address of the instruction being executed.
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    pub fn relative_base(&self) -> W {
+        self.relative_base.clone()
+    }
+
+    pub fn set_relative_base(&mut self, base: W) {
+        *self.relative_base = base;
+    }
+
+    pub fn read(&mut self, address: W) -> Result<W, InterpreterError<W>> {
+        let (pointer, opcode) = (self.pointer, &self.opcode);
+        self.memory
+            .read(address)
+            .map_err(|fault| fault.at(pointer, opcode.clone()))
+    }
+
+    pub fn write(&mut self, address: W, value: W) -> Result<(), InterpreterError<W>> {
+        let (pointer, opcode) = (self.pointer, &self.opcode);
+        let fault = |fault: Fault<W>| fault.at(pointer, opcode.clone());
+        let cell = to_address(address.clone()).map_err(fault)?;
+        let old = self.memory.get(address.clone());
+        self.memory.write(address, value).map_err(fault)?;
+        self.writes.push((cell, old));
+        Ok(())
+    }
+
+    /// Takes the next input, from the input queue or the input device. If
+    /// there is none, the handler should return the error: the instruction is
+    /// then undone, and `run` reports `NeedsInput` so that it runs again once
+    /// input is added.
+    pub fn input(&mut self) -> Result<W, InterpreterError<W>> {
+        let (pointer, opcode) = (self.pointer, &self.opcode);
+        let value = next_input(self.input, self.input_device).ok_or_else(|| {
+            InterpreterError::InputExhausted {
+                pointer,
+                opcode: opcode.clone(),
+            }
+        })?;
+        self.inputs.push(value.clone());
+        Ok(value)
+    }
+
+    /// Writes an output to the output device, or queues it. `run` returns
+    /// queued outputs one at a time, like those of `Out`.
+    pub fn output(&mut self, value: W) {
+        if self.output_device.is_none() {
+            self.queued_outputs += 1;
+        }
+        send_output(self.output, self.output_device, value);
+    }
+
+    /// Continues at `address` instead of the next instruction.
+    pub fn jump(&mut self, address: W) -> Result<(), InterpreterError<W>> {
+        let (pointer, opcode) = (self.pointer, &self.opcode);
+        self.next = to_address(address).map_err(|fault| fault.at(pointer, opcode.clone()))?;
+        Ok(())
+    }
+
+    /// Stops the program once the instruction is done.
+    pub fn halt(&mut self) {
+        *self.halt = true;
+    }
+
+    /// An error for the instruction being executed.
+    pub fn error(&self, message: &str) -> InterpreterError<W> {
+        InterpreterError::Extension {
+            pointer: self.pointer,
+            opcode: self.opcode.clone(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl<W: Word> Interpreter<W> {
+    /// Runs `handler` for instructions with `opcode`, which take one
+    /// parameter per entry of `roles`, with the usual parameter modes. The
+    /// handler gets the values of the read parameters and the addresses of
+    /// the written ones. Memory writes made by the handler can be undone with
+    /// `step_back`, inputs and outputs can't.
+    ///
+    /// Panics if `opcode` is a built-in opcode or not in `1..100`, or if the
+    /// instruction takes more than 3 parameters.
+    pub fn with_extension<F>(mut self, opcode: i64, roles: &[Role], handler: F) -> Self
+    where
+        F: FnMut(&mut Machine<W>, &[W]) -> Result<(), InterpreterError<W>> + Send + 'static,
+    {
+        assert!(
+            opcode > 0 && opcode < 100,
+            "extension opcode {} must be in 1..100",
+            opcode
+        );
+        assert!(
+            Self::decode(&[W::from_i64(opcode)], 0, DecodeMode::Permissive, Isa::Day9).is_err(),
+            "opcode {} is built in",
+            opcode
+        );
+        assert!(
+            roles.len() < MAX_INSTRUCTION_SIZE,
+            "extensions take at most {} parameters",
+            MAX_INSTRUCTION_SIZE - 1
+        );
+        self.extensions.insert(
+            opcode,
+            Extension {
+                roles: roles.to_vec(),
+                handler: Box::new(handler),
+            },
+        );
+        self.memory.decoded.clear();
+        self
+    }
+
+    /// Decodes the instruction at `pointer` if its opcode is registered.
+    pub(super) fn decode_extension(
+        &self,
+        pointer: usize,
+    ) -> Option<Result<Instruction<W>, InterpreterError<W>>> {
+        if self.extensions.is_empty() {
+            return None;
+        }
+        let params = ParamReader::new(&self.memory.cells, pointer, self.decoding, self.isa);
+        // The reader has already split the parameter modes off the opcode
+        let code = params.code;
+        let extension = self.extensions.get(&code)?;
+        let decode = || {
+            let mut decoded = Vec::with_capacity(extension.roles.len());
+            for (index, role) in extension.roles.iter().enumerate() {
+                decoded.push(match role {
+                    Role::Read => params.read(index + 1)?,
+                    Role::Write => params.write(index + 1)?,
+                });
+            }
+            params.check_unused(extension.roles.len())?;
+            Ok(Instruction::Extension {
+                opcode: code,
+                params: decoded,
+                roles: extension.roles.clone(),
+            })
+        };
+        Some(decode())
+    }
+
+    /// Resolves the operands of an extension instruction and runs its
+    /// handler, returning the address of the next instruction. A handler
+    /// that runs out of input is undone, except for outputs already written
+    /// to the output device.
+    pub(super) fn execute_extension(
+        &mut self,
+        instruction: &Instruction<W>,
+    ) -> Result<usize, InterpreterError<W>> {
+        let (code, params, roles) = match instruction {
+            Instruction::Extension {
+                opcode,
+                params,
+                roles,
+            } => (*opcode, params, roles),
+            _ => unreachable!("not an extension instruction"),
+        };
+        let (pointer, opcode) = (self.pointer, self.raw_opcode());
+        let relative_base = self.relative_base.clone();
+        let mut operands = Vec::with_capacity(params.len());
+        for (param, role) in params.iter().zip(roles) {
+            let operand = match role {
+                Role::Read => param.evaluate_r(&mut self.memory, &relative_base),
+                Role::Write => param.evaluate_w(&mut self.memory, &relative_base),
+            };
+            operands.push(operand.map_err(|fault| fault.at(pointer, opcode.clone()))?);
+        }
+
+        let extension = self
+            .extensions
+            .get_mut(&code)
+            .expect("decoded extensions are registered");
+        let (memory_len, halt) = (self.memory.cells.len(), self.halt);
+        let mut machine = Machine {
+            memory: &mut self.memory,
+            input: &mut self.input,
+            input_device: &mut self.input_device,
+            output: &mut self.output,
+            output_device: &mut self.output_device,
+            relative_base: &mut self.relative_base,
+            halt: &mut self.halt,
+            pointer,
+            opcode,
+            next: pointer + instruction.instruction_size(),
+            writes: Vec::new(),
+            inputs: Vec::new(),
+            queued_outputs: 0,
+        };
+        let result = (extension.handler)(&mut machine, &operands);
+        let Machine {
+            next,
+            writes,
+            inputs,
+            queued_outputs,
+            ..
+        } = machine;
+        match result {
+            Ok(()) => Ok(next),
+            Err(err @ InterpreterError::InputExhausted { .. }) => {
+                for (cell, old) in writes.into_iter().rev() {
+                    self.memory.restore(cell, old);
+                }
+                // The cells past the old end were 0 before they were written
+                self.memory.cells.truncate(memory_len);
+                for value in inputs.into_iter().rev() {
+                    self.input.push_front(value);
+                }
+                for _ in 0..queued_outputs {
+                    self.output.pop_back();
+                }
+                self.relative_base = relative_base;
+                self.halt = halt;
+                Err(err)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::RunStatus;
+    use super::*;
+
+    #[test]
+    fn extension_instructions() {
+        // Opcode 42 stores the sum of the squares of its two inputs, opcode
+        // 43 outputs a value twice
+        let mut interpreter = Interpreter::new(vec![1142, 3, 4, 7, 243, -2, 99, 0])
+            .with_extension(
+                42,
+                &[Role::Read, Role::Read, Role::Write],
+                |machine, ops| machine.write(ops[2], ops[0] * ops[0] + ops[1] * ops[1]),
+            )
+            .with_extension(43, &[Role::Read], |machine, ops| {
+                machine.output(ops[0]);
+                machine.output(ops[0]);
+                Ok(())
+            });
+        interpreter.relative_base = 9;
+        interpreter.execute_program().unwrap();
+        assert_eq!(25, interpreter.memory.get(7));
+        assert_eq!(vec![25, 25], interpreter.get_output());
+
+        // Still strict about modes
+        let mut interpreter = Interpreter::new(vec![10142, 3, 4, 7, 99]).with_extension(
+            42,
+            &[Role::Read, Role::Read, Role::Write],
+            |_, _| Ok(()),
+        );
+        assert_eq!(
+            Err(InterpreterError::ImmediateWrite {
+                pointer: 0,
+                opcode: 10142,
+                param: 3
+            }),
+            interpreter.execute_program()
+        );
+        let mut interpreter =
+            Interpreter::new(vec![1042, 3, 99]).with_extension(42, &[Role::Read], |_, _| Ok(()));
+        assert_eq!(
+            Err(InterpreterError::InvalidMode {
+                pointer: 0,
+                opcode: 1042,
+                param: 2,
+                mode: 1
+            }),
+            interpreter.execute_program()
+        );
+    }
+
+    // Opcode 50 jumps to its operand once an input of 0 is read, opcode 51
+    // fails
+    fn with_jumps(program: Vec<i64>) -> Interpreter {
+        Interpreter::new(program)
+            .with_extension(50, &[Role::Read], |machine, ops| {
+                if machine.input()? == 0 {
+                    machine.jump(ops[0])?;
+                }
+                Ok(())
+            })
+            .with_extension(51, &[], |machine, _| Err(machine.error("unsupported")))
+    }
+
+    #[test]
+    fn machine_access() {
+        let mut interpreter = with_jumps(vec![150, 5, 104, 1, 99, 51, 99]);
+        interpreter.add_input(1);
+        interpreter.execute_program().unwrap();
+        assert_eq!(vec![1], interpreter.get_output());
+
+        let mut interpreter = with_jumps(vec![150, 5, 104, 1, 99, 51, 99]);
+        interpreter.add_input(0);
+        let err = interpreter.execute_program().unwrap_err();
+        assert_eq!(
+            InterpreterError::Extension {
+                pointer: 5,
+                opcode: 51,
+                message: "unsupported".to_string()
+            },
+            err
+        );
+        assert_eq!("unsupported in opcode 51 at 5", err.to_string());
+
+        // Unregistered opcodes still fail
+        let mut interpreter = with_jumps(vec![52, 99]);
+        assert_eq!(
+            Err(InterpreterError::UnknownOpcode {
+                pointer: 0,
+                opcode: 52
+            }),
+            interpreter.execute_program()
+        );
+    }
+
+    #[test]
+    fn run_with_extensions() {
+        // Opcode 60 writes 1 to its operand, then adds an input to it and
+        // outputs the sum twice
+        let mut interpreter = Interpreter::new(vec![60, 5, 104, 7, 99, 0]).with_extension(
+            60,
+            &[Role::Write],
+            |machine, ops| {
+                machine.write(ops[0], 1)?;
+                let sum = machine.input()? + 1;
+                machine.write(ops[0], sum)?;
+                machine.output(sum);
+                machine.output(sum);
+                Ok(())
+            },
+        );
+        assert_eq!(Ok(RunStatus::NeedsInput), interpreter.run());
+        assert_eq!(0, interpreter.pointer);
+        assert_eq!(0, interpreter.memory.get(5));
+        assert_eq!(0, interpreter.instructions_executed());
+
+        interpreter.add_input(5);
+        assert_eq!(Ok(RunStatus::Output(6)), interpreter.run());
+        assert_eq!(Ok(RunStatus::Output(6)), interpreter.run());
+        assert_eq!(Ok(RunStatus::Output(7)), interpreter.run());
+        assert_eq!(Ok(RunStatus::Halted), interpreter.run());
+        assert_eq!(6, interpreter.memory.get(5));
+        assert!(interpreter.get_output().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "opcode 4 is built in")]
+    fn builtin_opcodes() {
+        Interpreter::new(vec![99]).with_extension(4, &[Role::Read], |_, _| Ok(()));
+    }
+}
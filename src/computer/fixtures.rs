@@ -0,0 +1,8 @@
+//! Programs shared by the tests of several modules.
+
+/// Counts cell 14 up to 3 in a loop, then outputs it.
+pub(super) fn count_to_three() -> Vec<i64> {
+    vec![
+        1001, 14, 1, 14, 1008, 14, 3, 15, 1006, 15, 0, 4, 14, 99, 0, 0,
+    ]
+}
@@ -0,0 +1,273 @@
+use super::{Access, Instruction, Interpreter, Word};
+use std::collections::VecDeque;
+
+/// What an executed instruction changed, so that it can be undone.
+struct Step<W> {
+    pointer: usize,
+    relative_base: W,
+    halt: bool,
+    /// Number of memory cells, which a write past the end grows.
+    memory_len: usize,
+    /// Every cell written, with its previous value.
+    writes: Vec<(usize, W)>,
+    input: Option<W>,
+    /// Whether the instruction was an `Out` that queued its output.
+    output: bool,
+    /// Whether that output is still in the output queue.
+    queued_output: bool,
+}
+
+/// The most recent steps of an `Interpreter`, oldest first.
+pub(super) struct History<W> {
+    steps: VecDeque<Step<W>>,
+    capacity: usize,
+}
+
+impl<W: Word> History<W> {
+    pub fn record(
+        &mut self,
+        instruction: &Instruction<W>,
+        (pointer, relative_base, halt, memory_len): (usize, W, bool, usize),
+        log: &[Access<W>],
+        queued_output: bool,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.steps.len() == self.capacity {
+            self.steps.pop_front();
+        }
+        let writes: Vec<(usize, W)> = log
+            .iter()
+            .filter_map(|access| match access {
+                Access::Write { address, old, .. } => Some((*address, old.clone())),
+                Access::Read { .. } => None,
+            })
+            .collect();
+        let input = match (instruction, log.last()) {
+            (Instruction::Inp(_), Some(Access::Write { new, .. })) => Some(new.clone()),
+            _ => None,
+        };
+        self.steps.push_back(Step {
+            pointer,
+            relative_base,
+            halt,
+            memory_len,
+            writes,
+            input,
+            output: queued_output,
+            queued_output,
+        });
+    }
+
+    /// Notes that the output of the last step was taken out of the queue.
+    pub fn output_taken(&mut self, taken: bool) {
+        if let Some(step) = self.steps.back_mut().filter(|step| step.output) {
+            step.queued_output = !taken;
+        }
+    }
+}
+
+impl<W: Word> Interpreter<W> {
+    /// Records the last `capacity` executed instructions, so that they can
+    /// be undone with `step_back` and `run_back_to_write`. Values written to
+    /// an output device can't be taken back, and inputs read from an input
+    /// device are put back into the input queue. Changes made to the memory,
+    /// pointer or relative base from outside aren't recorded, so whoever
+    /// makes them should call `clear_history`.
+    pub fn with_history(mut self, capacity: usize) -> Self {
+        self.memory.log.get_or_insert_with(Vec::new);
+        self.history = Some(History {
+            steps: VecDeque::with_capacity(capacity.min(1 << 16)),
+            capacity,
+        });
+        self
+    }
+
+    /// How many instructions can be undone.
+    pub fn history_len(&self) -> usize {
+        self.history
+            .as_ref()
+            .map_or(0, |history| history.steps.len())
+    }
+
+    /// Forgets every recorded instruction, as after an edit that they can't
+    /// be undone across.
+    pub fn clear_history(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.steps.clear();
+        }
+        self.reset_loop_detector();
+    }
+
+    /// Forgets the states loop detection has seen. After an undo or an edit
+    /// the machine may come back to one of them without looping.
+    fn reset_loop_detector(&mut self) {
+        if let Some(detector) = &mut self.loop_detector {
+            detector.reset();
+            detector.found = None;
+        }
+    }
+
+    /// Undoes the last executed instruction, returning false if there is
+    /// nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        let step = match self
+            .history
+            .as_mut()
+            .and_then(|history| history.steps.pop_back())
+        {
+            Some(step) => step,
+            None => return false,
+        };
+        for (address, old) in step.writes.into_iter().rev() {
+            self.memory.restore(address, old);
+        }
+        // The cells past the old end were 0 before they were written
+        self.memory.cells.truncate(step.memory_len);
+        if let Some(input) = step.input {
+            self.input.push_front(input);
+        }
+        if step.queued_output {
+            self.output.pop_back();
+        }
+        self.pointer = step.pointer;
+        self.relative_base = step.relative_base;
+        self.halt = step.halt;
+        self.executed -= 1;
+        self.reset_loop_detector();
+        true
+    }
+
+    /// Undoes instructions until the last one that wrote to `address`, so
+    /// that it is the next instruction to run. Returns false, without undoing
+    /// anything, if no recorded instruction wrote there.
+    pub fn run_back_to_write(&mut self, address: usize) -> bool {
+        let written = self.history.as_ref().is_some_and(|history| {
+            history
+                .steps
+                .iter()
+                .any(|step| step.writes.iter().any(|(cell, _)| *cell == address))
+        });
+        if !written {
+            return false;
+        }
+        while let Some(history) = &self.history {
+            let last = history.steps.back().expect("a write to undo");
+            let found = last.writes.iter().any(|(cell, _)| *cell == address);
+            self.step_back();
+            if found {
+                break;
+            }
+        }
+        true
+    }
+
+    /// Puts an output returned by `run` back into the output queue, where
+    /// `step_back` will find it, followed by the outputs of the same
+    /// extension instruction that `run` has yet to return.
+    pub(super) fn requeue_output(&mut self, value: W) {
+        self.output.push_back(value);
+        self.output.extend(self.pending_output.drain(..));
+        if let Some(history) = &mut self.history {
+            history.output_taken(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::fixtures::count_to_three;
+    use super::super::{Limit, RunStatus};
+    use super::*;
+
+    // Reads a value, adds it to a running total, outputs the total and
+    // starts over
+    fn running_total() -> Vec<i64> {
+        vec![3, 12, 1, 12, 13, 13, 4, 13, 1105, 1, 0, 99, 0, 0]
+    }
+
+    #[test]
+    fn step_back() {
+        let mut interpreter = Interpreter::new(running_total()).with_history(100);
+        interpreter.add_input(5);
+        interpreter.add_input(7);
+        assert_eq!(Ok(RunStatus::Output(5)), interpreter.run());
+        interpreter.execute_program().unwrap_err();
+        assert_eq!(vec![12], interpreter.get_output());
+        assert_eq!(8, interpreter.history_len());
+
+        // Back to before the second output
+        assert!(interpreter.step_back());
+        assert!(interpreter.step_back());
+        assert!(interpreter.get_output().is_empty());
+        assert_eq!(6, interpreter.pointer);
+
+        // Back to before the second input was read
+        assert!(interpreter.run_back_to_write(12));
+        assert_eq!(0, interpreter.pointer);
+        assert_eq!(
+            vec![7],
+            interpreter.input.iter().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(5, interpreter.memory.get(13));
+        interpreter.execute_program().unwrap_err();
+        assert_eq!(vec![12], interpreter.get_output());
+
+        assert!(!interpreter.run_back_to_write(99));
+        while interpreter.step_back() {}
+        assert_eq!(running_total(), interpreter.memory.cells);
+        assert_eq!(0, interpreter.instructions_executed());
+    }
+
+    #[test]
+    fn bounded() {
+        let mut interpreter = Interpreter::new(running_total()).with_history(3);
+        for input in 1..10 {
+            interpreter.add_input(input);
+        }
+        interpreter.execute_program().unwrap_err();
+        assert_eq!(3, interpreter.history_len());
+        while interpreter.step_back() {}
+        assert_eq!(33, interpreter.instructions_executed());
+        assert_eq!(2, interpreter.pointer);
+    }
+
+    #[test]
+    fn shrinks_memory() {
+        // Writes past the end of memory, then halts
+        let program = vec![1101, 2, 3, 10, 99];
+        let mut interpreter = Interpreter::new(program.clone()).with_history(10);
+        interpreter.execute_program().unwrap();
+        assert_eq!(11, interpreter.memory.cells.len());
+        while interpreter.step_back() {}
+        assert_eq!(program, interpreter.memory.cells);
+    }
+
+    #[test]
+    fn undo_forgets_loop_states() {
+        // Running forward again reaches states seen before the undo
+        let mut interpreter = Interpreter::new(count_to_three())
+            .with_loop_detection()
+            .with_history(100);
+        assert_eq!(Ok(RunStatus::Output(3)), interpreter.run_steps(10));
+        for _ in 0..5 {
+            assert!(interpreter.step_back());
+        }
+        assert_eq!(Ok(RunStatus::Output(3)), interpreter.run());
+        assert_eq!(Ok(RunStatus::Halted), interpreter.run());
+
+        // Starting over from outside, as a debugger would
+        let mut interpreter = Interpreter::new(count_to_three()).with_loop_detection();
+        assert_eq!(
+            Ok(RunStatus::LimitReached(Limit::Steps)),
+            interpreter.run_steps(8)
+        );
+        for cell in 14..16 {
+            interpreter.memory.restore(cell, 0);
+        }
+        interpreter.pointer = 0;
+        interpreter.clear_history();
+        assert_eq!(Ok(RunStatus::Output(3)), interpreter.run());
+    }
+}
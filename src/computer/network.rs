@@ -0,0 +1,354 @@
+//! A network of addressed machines, as in day 23. Addresses and packet
+//! values are i64, and so is the -1 read from an empty queue, so the
+//! machines are `Interpreter<i64>`.
+
+use super::{Interpreter, InterpreterError, RunStatus};
+use std::error;
+use std::fmt;
+
+/// Instructions a machine may execute before the scheduler moves on to the
+/// next one.
+const TIME_SLICE: usize = 1000;
+
+/// A message of two values sent between addressed machines.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Packet {
+    pub destination: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+/// What the monitor is told about.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MonitorEvent {
+    Packet(Packet),
+    /// Every machine is polling an empty queue and no packet is moving.
+    Idle,
+}
+
+/// What the monitor wants the network to do next.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MonitorAction {
+    Continue,
+    Send(Packet),
+    Stop,
+}
+
+/// Why `Network::run` returned.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NetworkStatus {
+    /// The monitor asked to stop.
+    Stopped,
+    /// The network went idle and the monitor didn't wake it up.
+    Idle,
+    /// Every machine halted.
+    Halted,
+}
+
+/// A machine of the network failed.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MachineError {
+    pub address: usize,
+    pub error: InterpreterError,
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "machine {}: {}", self.address, self.error)
+    }
+}
+
+impl error::Error for MachineError {}
+
+struct Machine {
+    interpreter: Interpreter,
+    pending: Vec<i64>,
+    halted: bool,
+}
+
+type Monitor = Box<dyn FnMut(MonitorEvent) -> MonitorAction>;
+
+/// Runs addressed machines on a single thread, routing `(destination, x, y)`
+/// output triples into the input queue of the destination machine. A machine
+/// reading from an empty queue gets -1 and yields to the next one.
+pub struct Network {
+    machines: Vec<Machine>,
+    monitor_address: Option<i64>,
+    monitor: Option<Monitor>,
+    idle_rounds: usize,
+    dropped: Vec<Packet>,
+}
+
+impl Network {
+    /// Boots `size` copies of `program`, giving each its address as first
+    /// input.
+    pub fn new(program: &[i64], size: usize) -> Self {
+        let machines = (0..size)
+            .map(|address| {
+                let mut interpreter = Interpreter::new(program.to_vec());
+                interpreter.add_input(address as i64);
+                Machine {
+                    interpreter,
+                    pending: Vec::new(),
+                    halted: false,
+                }
+            })
+            .collect();
+        Self {
+            machines,
+            monitor_address: None,
+            monitor: None,
+            idle_rounds: 2,
+            dropped: Vec::new(),
+        }
+    }
+
+    /// Calls `monitor` with every packet sent to `address` and whenever the
+    /// network goes idle.
+    pub fn with_monitor<F>(mut self, address: i64, monitor: F) -> Self
+    where
+        F: FnMut(MonitorEvent) -> MonitorAction + 'static,
+    {
+        self.monitor_address = Some(address);
+        self.monitor = Some(Box::new(monitor));
+        self
+    }
+
+    /// How many consecutive rounds without traffic make the network idle.
+    pub fn with_idle_rounds(mut self, rounds: usize) -> Self {
+        self.idle_rounds = rounds.max(1);
+        self
+    }
+
+    /// Packets sent to an address with no machine and no monitor.
+    pub fn dropped(&self) -> &[Packet] {
+        &self.dropped
+    }
+
+    pub fn send(&mut self, packet: Packet) {
+        match self.machine_index(packet.destination) {
+            Some(index) => {
+                let interpreter = &mut self.machines[index].interpreter;
+                interpreter.add_input(packet.x);
+                interpreter.add_input(packet.y);
+            }
+            None => self.dropped.push(packet),
+        }
+    }
+
+    fn machine_index(&self, address: i64) -> Option<usize> {
+        if address >= 0 && (address as usize) < self.machines.len() {
+            Some(address as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn run(&mut self) -> Result<NetworkStatus, MachineError> {
+        let mut quiet_rounds = 0;
+        loop {
+            let mut traffic = false;
+            let mut all_polling = true;
+            for address in 0..self.machines.len() {
+                let (sent, polled) = self.run_machine(address)?;
+                for packet in sent {
+                    traffic = true;
+                    if Some(packet.destination) == self.monitor_address {
+                        match self.notify(MonitorEvent::Packet(packet)) {
+                            MonitorAction::Continue => {}
+                            MonitorAction::Send(packet) => self.send(packet),
+                            MonitorAction::Stop => return Ok(NetworkStatus::Stopped),
+                        }
+                    } else {
+                        self.send(packet);
+                    }
+                }
+                all_polling &= polled || self.machines[address].halted;
+            }
+
+            if self.machines.iter().all(|machine| machine.halted) {
+                return Ok(NetworkStatus::Halted);
+            }
+            let queues_empty = self
+                .machines
+                .iter()
+                .all(|machine| machine.interpreter.input.is_empty());
+            if traffic || !all_polling || !queues_empty {
+                quiet_rounds = 0;
+                continue;
+            }
+            quiet_rounds += 1;
+            if quiet_rounds >= self.idle_rounds {
+                quiet_rounds = 0;
+                match self.notify(MonitorEvent::Idle) {
+                    MonitorAction::Send(packet) => self.send(packet),
+                    MonitorAction::Stop => return Ok(NetworkStatus::Stopped),
+                    MonitorAction::Continue => return Ok(NetworkStatus::Idle),
+                }
+            }
+        }
+    }
+
+    fn notify(&mut self, event: MonitorEvent) -> MonitorAction {
+        match &mut self.monitor {
+            Some(monitor) => monitor(event),
+            None => MonitorAction::Continue,
+        }
+    }
+
+    /// Runs one machine for a time slice or until it polls an empty queue.
+    /// Returns the packets it sent and whether it polled.
+    fn run_machine(&mut self, address: usize) -> Result<(Vec<Packet>, bool), MachineError> {
+        let machine = &mut self.machines[address];
+        let fail = |error| MachineError { address, error };
+        let mut sent = Vec::new();
+        let mut budget = TIME_SLICE;
+        while !machine.halted && budget > 0 {
+            let executed = machine.interpreter.instructions_executed();
+            let status = machine.interpreter.run_steps(budget).map_err(fail)?;
+            let used = machine.interpreter.instructions_executed() - executed;
+            budget = budget.saturating_sub(used as usize);
+            match status {
+                RunStatus::Output(value) => {
+                    machine.pending.push(value);
+                    if let [destination, x, y] = machine.pending[..] {
+                        sent.push(Packet { destination, x, y });
+                        machine.pending.clear();
+                    }
+                }
+                RunStatus::NeedsInput => {
+                    machine.interpreter.add_input(-1);
+                    machine.interpreter.run_steps(1).map_err(fail)?;
+                    return Ok((sent, true));
+                }
+                RunStatus::Halted => machine.halted = true,
+                RunStatus::LimitReached(_) | RunStatus::InfiniteLoop { .. } => break,
+            }
+        }
+        Ok((sent, false))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::assembler::assemble;
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Forwards every packet to the next address, adding 1 to its y value
+    const RELAY: &str = "
+                IN addr
+        loop:   IN x
+                EQ x, #-1, idle
+                JT idle, #loop
+                IN y
+                ADD addr, #1, dest
+                ADD y, #1, y
+                OUT dest
+                OUT x
+                OUT y
+                JT #1, #loop
+        addr:   DATA 0
+        x:      DATA 0
+        y:      DATA 0
+        idle:   DATA 0
+        dest:   DATA 0
+    ";
+
+    #[test]
+    fn relay_to_monitor() {
+        let program = assemble(RELAY).unwrap();
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let log = received.clone();
+        let mut wakeups = 0;
+        let mut network = Network::new(&program, 50).with_monitor(50, move |event| match event {
+            MonitorEvent::Packet(packet) => {
+                log.borrow_mut().push(packet);
+                MonitorAction::Continue
+            }
+            MonitorEvent::Idle if wakeups == 0 => {
+                wakeups += 1;
+                MonitorAction::Send(Packet {
+                    destination: 0,
+                    x: 8,
+                    y: 100,
+                })
+            }
+            MonitorEvent::Idle => MonitorAction::Stop,
+        });
+        network.send(Packet {
+            destination: 0,
+            x: 7,
+            y: 0,
+        });
+
+        assert_eq!(Ok(NetworkStatus::Stopped), network.run());
+        assert_eq!(
+            vec![
+                Packet {
+                    destination: 50,
+                    x: 7,
+                    y: 50
+                },
+                Packet {
+                    destination: 50,
+                    x: 8,
+                    y: 150
+                }
+            ],
+            *received.borrow()
+        );
+    }
+
+    #[test]
+    fn idle_without_monitor() {
+        let program = assemble(RELAY).unwrap();
+        let mut network = Network::new(&program, 3);
+        network.send(Packet {
+            destination: 1,
+            x: 1,
+            y: 1,
+        });
+        assert_eq!(Ok(NetworkStatus::Idle), network.run());
+        assert_eq!(
+            vec![Packet {
+                destination: 3,
+                x: 1,
+                y: 3
+            }],
+            network.dropped()
+        );
+    }
+
+    #[test]
+    fn time_slices() {
+        // Reads its address, then sends packets to machine 5 forever
+        let mut network = Network::new(&[3, 100, 104, 5, 1105, 1, 2], 2);
+        let (sent, polled) = network.run_machine(0).unwrap();
+        assert!(!polled);
+        assert_eq!(TIME_SLICE / 6, sent.len());
+        assert_eq!(
+            TIME_SLICE as u64,
+            network.machines[0].interpreter.instructions_executed()
+        );
+    }
+
+    #[test]
+    fn halting_and_failing_machines() {
+        let mut network = Network::new(&[3, 5, 99], 4);
+        assert_eq!(Ok(NetworkStatus::Halted), network.run());
+
+        let mut network = Network::new(&[3, 5, 4, 5, 98], 2);
+        assert_eq!(
+            Err(MachineError {
+                address: 0,
+                error: InterpreterError::UnknownOpcode {
+                    pointer: 4,
+                    opcode: 98
+                }
+            }),
+            network.run()
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use super::disassembler;
+use super::{Access, DecodeMode, Interpreter, Isa, Word};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Execution counts collected by `Interpreter::with_profiler`.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Profile {
+    pub instructions: u64,
+    /// Executions of the instruction at each address.
+    pub addresses: HashMap<usize, u64>,
+    /// Executions of each opcode, without its parameter modes.
+    pub opcodes: HashMap<i64, u64>,
+    pub reads: HashMap<usize, u64>,
+    pub writes: HashMap<usize, u64>,
+}
+
+impl Profile {
+    pub(super) fn record<W>(&mut self, pointer: usize, opcode: i64, log: &[Access<W>]) {
+        self.instructions += 1;
+        *self.addresses.entry(pointer).or_insert(0) += 1;
+        *self.opcodes.entry(opcode % 100).or_insert(0) += 1;
+        for access in log {
+            let counts = match access {
+                Access::Read { .. } => &mut self.reads,
+                Access::Write { .. } => &mut self.writes,
+            };
+            *counts.entry(access.address()).or_insert(0) += 1;
+        }
+    }
+
+    /// Addresses by how often their instruction ran, most executed first.
+    pub fn hot_spots(&self) -> Vec<(usize, u64)> {
+        sorted(&self.addresses)
+    }
+
+    /// Memory cells by how often they were read or written, most accessed
+    /// first, as `(address, reads, writes)`.
+    pub fn hot_cells(&self) -> Vec<(usize, u64, u64)> {
+        let mut cells: Vec<(usize, u64, u64)> = self
+            .reads
+            .keys()
+            .chain(
+                self.writes
+                    .keys()
+                    .filter(|address| !self.reads.contains_key(address)),
+            )
+            .map(|address| {
+                let count =
+                    |counts: &HashMap<usize, u64>| counts.get(address).cloned().unwrap_or(0);
+                (*address, count(&self.reads), count(&self.writes))
+            })
+            .collect();
+        cells.sort_by_key(|&(address, reads, writes)| (std::cmp::Reverse(reads + writes), address));
+        cells
+    }
+
+    /// A report of the `top` hottest addresses, with the instruction found
+    /// there in `memory`, followed by the opcode counts and the `top` most
+    /// accessed memory cells.
+    pub fn report<W: Word>(&self, memory: &[W], top: usize) -> String {
+        let percent = |count: u64| 100.0 * count as f64 / self.instructions.max(1) as f64;
+        let mut report = format!("{} instructions executed\n", self.instructions);
+
+        report.push_str("\nhot spots:\n");
+        for (address, count) in self.hot_spots().into_iter().take(top) {
+            let text = if address < memory.len() {
+                disassembler::decode_line(memory, address).1
+            } else {
+                String::new()
+            };
+            writeln!(
+                report,
+                "{:>12} {:>6.2}% {:>5}: {}",
+                count,
+                percent(count),
+                address,
+                text
+            )
+            .unwrap();
+        }
+
+        report.push_str("\nopcodes:\n");
+        for (opcode, count) in sorted(&self.opcodes) {
+            let mnemonic = Interpreter::decode(&[opcode], 0, DecodeMode::Strict, Isa::Day9)
+                .map(|instruction| instruction.mnemonic())
+                .unwrap_or("???");
+            writeln!(
+                report,
+                "{:>12} {:>6.2}% {:<3} ({})",
+                count,
+                percent(count),
+                mnemonic,
+                opcode
+            )
+            .unwrap();
+        }
+
+        report.push_str("\nmemory:         reads       writes\n");
+        for (address, reads, writes) in self.hot_cells().into_iter().take(top) {
+            writeln!(report, "{:>6} {:>12} {:>12}", address, reads, writes).unwrap();
+        }
+        report
+    }
+}
+
+fn sorted<K: Copy + Ord>(counts: &HashMap<K, u64>) -> Vec<(K, u64)> {
+    let mut counts: Vec<(K, u64)> = counts.iter().map(|(key, count)| (*key, *count)).collect();
+    counts.sort_by_key(|&(key, count)| (std::cmp::Reverse(count), key));
+    counts
+}
+
+impl<W: Word> Interpreter<W> {
+    /// Counts executions per address and opcode and accesses per memory
+    /// cell, see `profile` and `profile_report`.
+    pub fn with_profiler(mut self) -> Self {
+        self.memory.log.get_or_insert_with(Vec::new);
+        self.profile = Some(Profile::default());
+        self
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// The profile report for the `top` hottest addresses and cells, if the
+    /// profiler is on.
+    pub fn profile_report(&self, top: usize) -> Option<String> {
+        Some(self.profile.as_ref()?.report(&self.memory.cells, top))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::fixtures::count_to_three;
+    use super::*;
+
+    #[test]
+    fn counts() {
+        let mut interpreter = Interpreter::new(count_to_three()).with_profiler();
+        interpreter.execute_program().unwrap();
+        let profile = interpreter.profile().unwrap();
+
+        assert_eq!(11, profile.instructions);
+        assert_eq!(
+            vec![(0, 3), (4, 3), (8, 3), (11, 1), (13, 1)],
+            profile.hot_spots()
+        );
+        assert_eq!(Some(&3), profile.opcodes.get(&1));
+        assert_eq!(Some(&1), profile.opcodes.get(&99));
+        assert_eq!(vec![(14, 7, 3), (15, 3, 3)], profile.hot_cells());
+    }
+
+    #[test]
+    fn report() {
+        let mut interpreter = Interpreter::new(vec![1101, 2, 3, 5, 99, 0]).with_profiler();
+        interpreter.execute_program().unwrap();
+        assert_eq!(
+            "2 instructions executed
+
+hot spots:
+           1  50.00%     0: ADD #2, #3, 5
+           1  50.00%     4: HLT
+
+opcodes:
+           1  50.00% ADD (1)
+           1  50.00% HLT (99)
+
+memory:         reads       writes
+     5            0            1
+",
+            interpreter.profile_report(10).unwrap()
+        );
+        assert_eq!(None, Interpreter::new(vec![99]).profile_report(10));
+
+        let mut wide = Interpreter::from_words(vec![1101_i128, 2, 3, 5, 99, 0]).with_profiler();
+        wide.execute_program().unwrap();
+        assert_eq!(interpreter.profile_report(10), wide.profile_report(10));
+    }
+}
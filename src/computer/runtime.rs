@@ -0,0 +1,299 @@
+use super::{Interpreter, InterpreterError, RunStatus, Word};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How often a machine blocked on input checks whether the whole network
+/// has deadlocked.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// How a machine ended, along with the machine itself and any values sent to
+/// it that it never read.
+pub struct MachineReport<W = i64> {
+    pub id: usize,
+    /// `Halted`, or `NeedsInput` when it was starved by every sender
+    /// finishing or by a deadlock.
+    pub status: Result<RunStatus<W>, InterpreterError<W>>,
+    pub interpreter: Interpreter<W>,
+    pub unread: Vec<W>,
+}
+
+/// Book-keeping shared by all machines to detect that every running machine
+/// is waiting for input that nobody is going to send.
+struct Network {
+    running: usize,
+    waiting: usize,
+    /// Values sent to running machines that they haven't received yet.
+    in_flight: usize,
+    finished: Vec<bool>,
+    deadlocked: bool,
+}
+
+impl Network {
+    fn send<W>(&mut self, to: usize, sender: &Sender<W>, value: W) {
+        if sender.send(value).is_ok() && !self.finished[to] {
+            self.in_flight += 1;
+        }
+    }
+
+    fn finish<W>(&mut self, id: usize, receiver: &Receiver<W>, unread: &mut Vec<W>) {
+        self.running -= 1;
+        self.finished[id] = true;
+        for value in receiver.try_iter() {
+            self.in_flight -= 1;
+            unread.push(value);
+        }
+    }
+
+    fn check_deadlock(&mut self) -> bool {
+        if self.running > 0 && self.waiting == self.running && self.in_flight == 0 {
+            self.deadlocked = true;
+        }
+        self.deadlocked
+    }
+}
+
+/// Runs every `Interpreter` on its own thread, connecting outputs to inputs
+/// with blocking channels.
+pub struct Runtime<W = i64> {
+    machines: Vec<Interpreter<W>>,
+    links: Vec<(usize, usize)>,
+}
+
+impl<W: Word> Default for Runtime<W> {
+    fn default() -> Self {
+        Self {
+            machines: Vec::new(),
+            links: Vec::new(),
+        }
+    }
+}
+
+impl<W: Word> Runtime<W> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a machine, returning the id used to connect it.
+    pub fn add(&mut self, interpreter: Interpreter<W>) -> usize {
+        self.machines.push(interpreter);
+        self.machines.len() - 1
+    }
+
+    /// Sends every output of `from` to the input of `to`. Machines whose
+    /// outputs go nowhere keep them in their output queue.
+    pub fn connect(&mut self, from: usize, to: usize) {
+        self.links.push((from, to));
+    }
+
+    /// Runs all machines until each one halts, fails or is starved of input,
+    /// and returns their reports ordered by id.
+    pub fn run(self) -> Vec<MachineReport<W>> {
+        let Runtime { machines, links } = self;
+        let network = Arc::new(Mutex::new(Network {
+            running: machines.len(),
+            waiting: 0,
+            in_flight: 0,
+            finished: vec![false; machines.len()],
+            deadlocked: false,
+        }));
+        let (senders, receivers): (Vec<Sender<W>>, Vec<Receiver<W>>) =
+            machines.iter().map(|_| mpsc::channel()).unzip();
+
+        let handles: Vec<_> = machines
+            .into_iter()
+            .zip(receivers)
+            .enumerate()
+            .map(|(id, (interpreter, receiver))| {
+                let outputs: Vec<(usize, Sender<W>)> = links
+                    .iter()
+                    .filter(|(from, _)| *from == id)
+                    .map(|(_, to)| (*to, senders[*to].clone()))
+                    .collect();
+                let network = network.clone();
+                thread::spawn(move || run_machine(id, interpreter, receiver, outputs, network))
+            })
+            .collect();
+        drop(senders);
+
+        let finished: Vec<_> = handles
+            .into_iter()
+            .map(|handle| handle.join().expect("machine thread panicked"))
+            .collect();
+        finished
+            .into_iter()
+            .enumerate()
+            .map(|(id, (interpreter, status, receiver, mut unread))| {
+                unread.extend(receiver.try_iter());
+                MachineReport {
+                    id,
+                    status,
+                    interpreter,
+                    unread,
+                }
+            })
+            .collect()
+    }
+}
+
+type Finished<W> = (
+    Interpreter<W>,
+    Result<RunStatus<W>, InterpreterError<W>>,
+    Receiver<W>,
+    Vec<W>,
+);
+
+fn run_machine<W: Word>(
+    id: usize,
+    mut interpreter: Interpreter<W>,
+    receiver: Receiver<W>,
+    outputs: Vec<(usize, Sender<W>)>,
+    network: Arc<Mutex<Network>>,
+) -> Finished<W> {
+    let status = loop {
+        match interpreter.run() {
+            Ok(RunStatus::Output(value)) if outputs.is_empty() => {
+                interpreter.output.push_back(value)
+            }
+            Ok(RunStatus::Output(value)) => {
+                let mut network = network.lock().unwrap();
+                for (to, sender) in &outputs {
+                    network.send(*to, sender, value.clone());
+                }
+            }
+            Ok(RunStatus::NeedsInput) => match receive(&receiver, &network) {
+                Some(value) => interpreter.add_input(value),
+                None => break Ok(RunStatus::NeedsInput),
+            },
+            status => break status,
+        }
+    };
+    let mut unread = Vec::new();
+    network.lock().unwrap().finish(id, &receiver, &mut unread);
+    // Dropping the senders lets the machines downstream see the end of input
+    drop(outputs);
+    (interpreter, status, receiver, unread)
+}
+
+fn receive<W>(receiver: &Receiver<W>, network: &Mutex<Network>) -> Option<W> {
+    network.lock().unwrap().waiting += 1;
+    loop {
+        let received = receiver.recv_timeout(POLL_INTERVAL);
+        // Both counters change under one lock, so that no machine sees this
+        // one waiting with nothing in flight after it got a value
+        let mut state = network.lock().unwrap();
+        let value = match received {
+            Ok(value) => Some(value),
+            Err(RecvTimeoutError::Disconnected) => None,
+            // Values are sent under the lock, so one that arrived since the
+            // timeout is still counted as in flight
+            Err(RecvTimeoutError::Timeout) => match receiver.try_recv() {
+                Ok(value) => Some(value),
+                Err(_) if state.check_deadlock() => None,
+                Err(_) => continue,
+            },
+        };
+        if value.is_some() {
+            state.in_flight -= 1;
+        }
+        state.waiting -= 1;
+        return value;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Outputs every input doubled until it reads a 0, which it passes on
+    // before halting
+    fn double_and_pass_zero() -> Vec<i64> {
+        vec![
+            3, 17, 1002, 17, 2, 18, 4, 18, 1005, 17, 0, 99, 0, 0, 0, 0, 0, 0, 0,
+        ]
+    }
+
+    #[test]
+    fn pipeline() {
+        let mut runtime = Runtime::new();
+        let mut first = Interpreter::new(double_and_pass_zero());
+        first.add_input(1);
+        first.add_input(5);
+        first.add_input(0);
+        let mut ids = vec![runtime.add(first)];
+        for _ in 0..9 {
+            ids.push(runtime.add(Interpreter::new(double_and_pass_zero())));
+        }
+        for pair in ids.windows(2) {
+            runtime.connect(pair[0], pair[1]);
+        }
+
+        let reports = runtime.run();
+        assert_eq!(10, reports.len());
+        for report in &reports {
+            assert_eq!(Ok(RunStatus::Halted), report.status);
+        }
+        assert_eq!(
+            vec![1024, 5120, 0],
+            reports[9]
+                .interpreter
+                .output
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn deadlock() {
+        // Two machines that both wait for the other to speak first
+        let mut runtime = Runtime::new();
+        let a = runtime.add(Interpreter::new(double_and_pass_zero()));
+        let b = runtime.add(Interpreter::new(double_and_pass_zero()));
+        runtime.connect(a, b);
+        runtime.connect(b, a);
+
+        let reports = runtime.run();
+        assert_eq!(Ok(RunStatus::NeedsInput), reports[a].status);
+        assert_eq!(Ok(RunStatus::NeedsInput), reports[b].status);
+    }
+
+    #[test]
+    fn unread_values_and_errors() {
+        let mut runtime = Runtime::new();
+        let mut source = Interpreter::new(vec![104, 7, 104, 8, 99]);
+        source.add_input(0);
+        let source = runtime.add(source);
+        let broken = runtime.add(Interpreter::new(vec![42]));
+        runtime.connect(source, broken);
+
+        let reports = runtime.run();
+        assert_eq!(Ok(RunStatus::Halted), reports[source].status);
+        assert!(reports[broken].status.is_err());
+        assert_eq!(vec![7, 8], reports[broken].unread);
+    }
+
+    #[test]
+    fn wide_words() {
+        let program: Vec<i128> = double_and_pass_zero().into_iter().map(i128::from).collect();
+        let mut runtime = Runtime::new();
+        let mut first = Interpreter::from_words(program.clone());
+        first.add_input(i128::from(i64::MAX));
+        first.add_input(0);
+        let first = runtime.add(first);
+        let second = runtime.add(Interpreter::from_words(program));
+        runtime.connect(first, second);
+
+        let reports = runtime.run();
+        assert_eq!(
+            vec![i128::from(i64::MAX) * 4, 0],
+            reports[second]
+                .interpreter
+                .output
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+        );
+    }
+}
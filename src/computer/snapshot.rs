@@ -0,0 +1,347 @@
+use super::{DecodeMode, Interpreter, Isa, Memory, Overflow, Word};
+use std::collections::VecDeque;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &str = "intcode-snapshot";
+const VERSION: u32 = 2;
+
+/// Errors from reading a snapshot back.
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    UnsupportedVersion(String),
+    Malformed { line: usize, message: String },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "{}", err),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::Malformed { line, message } => {
+                write!(f, "malformed snapshot at line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+impl<W: Word> Interpreter<W> {
+    /// Serializes the complete machine state: memory, registers, queues,
+    /// halt flags, the instruction count and limit, and the decoding, ISA
+    /// and overflow settings. Traces, I/O devices, extensions, the time
+    /// limit, loop detection, the profile and the history are not part of a
+    /// snapshot.
+    ///
+    /// The format is line based text, starting with `intcode-snapshot <version>`
+    /// followed by one `key value` line per field.
+    pub fn to_snapshot(&self) -> String {
+        format!(
+            "{} {}\npointer {}\nrelative_base {}\nhalt {}\nhalt_on_output {}\nmemory_limit {}\nexecuted {}\ninstruction_limit {}\ndecoding {}\nisa {}\noverflow {}\ninput {}\noutput {}\nmemory {}\n",
+            MAGIC,
+            VERSION,
+            self.pointer,
+            self.relative_base,
+            self.halt,
+            self.halt_on_output,
+            optional(self.memory.limit),
+            self.executed,
+            optional(self.instruction_limit),
+            name(&DECODE_MODES, self.decoding),
+            name(&ISAS, self.isa),
+            name(&OVERFLOWS, self.overflow),
+            join(self.input.iter()),
+            join(self.output.iter().chain(&self.pending_output)),
+            join(self.memory.cells.iter()),
+        )
+    }
+
+    /// Reads a snapshot back. Version 1 snapshots have no instruction count
+    /// or settings, so those start from their defaults.
+    pub fn from_snapshot(snapshot: &str) -> Result<Self, SnapshotError> {
+        let mut lines = snapshot
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line));
+
+        let version = match lines.next() {
+            Some((_, header)) if header.starts_with(MAGIC) => {
+                let version = header[MAGIC.len()..].trim();
+                match version.parse::<u32>() {
+                    Ok(number) if (1..=VERSION).contains(&number) => number,
+                    _ => return Err(SnapshotError::UnsupportedVersion(version.to_string())),
+                }
+            }
+            _ => return Err(malformed(1, "missing snapshot header")),
+        };
+
+        let end = snapshot.lines().count() + 1;
+        let mut field = |name: &str| -> Result<(usize, String), SnapshotError> {
+            let (line, text) = lines
+                .next()
+                .ok_or_else(|| malformed(end, &format!("missing field {}", name)))?;
+            let mut parts = text.splitn(2, ' ');
+            if parts.next() != Some(name) {
+                return Err(malformed(line, &format!("expected field {}", name)));
+            }
+            Ok((line, parts.next().unwrap_or("").to_string()))
+        };
+
+        let pointer = parse_value(field("pointer")?)?;
+        let relative_base = parse_value(field("relative_base")?)?;
+        let halt = parse_value(field("halt")?)?;
+        let halt_on_output = parse_value(field("halt_on_output")?)?;
+        let limit = parse_optional(field("memory_limit")?)?;
+        // Version 1 has no instruction count or settings, use the defaults
+        let (executed, instruction_limit, decoding, isa, overflow) = if version == 1 {
+            (0, None, DecodeMode::Strict, Isa::Day9, Overflow::Trap)
+        } else {
+            (
+                parse_value(field("executed")?)?,
+                parse_optional(field("instruction_limit")?)?,
+                parse_name(&DECODE_MODES, field("decoding")?)?,
+                parse_name(&ISAS, field("isa")?)?,
+                parse_name(&OVERFLOWS, field("overflow")?)?,
+            )
+        };
+        let input = parse_list(field("input")?)?;
+        let output = parse_list(field("output")?)?;
+        let cells = parse_list(field("memory")?)?;
+
+        let mut interpreter = Interpreter::from_words(Vec::new())
+            .with_decode_mode(decoding)
+            .with_isa(isa)
+            .with_overflow(overflow);
+        interpreter.memory = Memory::new(cells);
+        interpreter.memory.limit = limit;
+        interpreter.pointer = pointer;
+        interpreter.relative_base = relative_base;
+        interpreter.halt = halt;
+        interpreter.halt_on_output = halt_on_output;
+        interpreter.executed = executed;
+        interpreter.instruction_limit = instruction_limit;
+        interpreter.input = input.into_iter().collect::<VecDeque<_>>();
+        interpreter.output = output.into_iter().collect::<VecDeque<_>>();
+        Ok(interpreter)
+    }
+
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_snapshot())
+    }
+
+    pub fn load_snapshot<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::from_snapshot(&fs::read_to_string(path)?)
+    }
+}
+
+const DECODE_MODES: [(DecodeMode, &str); 2] = [
+    (DecodeMode::Strict, "strict"),
+    (DecodeMode::Permissive, "permissive"),
+];
+
+const ISAS: [(Isa, &str); 3] = [
+    (Isa::Day2, "day2"),
+    (Isa::Day5, "day5"),
+    (Isa::Day9, "day9"),
+];
+
+const OVERFLOWS: [(Overflow, &str); 3] = [
+    (Overflow::Wrap, "wrap"),
+    (Overflow::Trap, "trap"),
+    (Overflow::Saturate, "saturate"),
+];
+
+fn name<T: PartialEq>(names: &[(T, &'static str)], value: T) -> &'static str {
+    names
+        .iter()
+        .find(|(named, _)| *named == value)
+        .map(|(_, name)| *name)
+        .expect("every setting has a name")
+}
+
+fn optional<T: fmt::Display>(value: Option<T>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "none".to_string(),
+    }
+}
+
+fn join<'a, W: Word>(values: impl Iterator<Item = &'a W>) -> String {
+    values
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn malformed(line: usize, message: &str) -> SnapshotError {
+    SnapshotError::Malformed {
+        line,
+        message: message.to_string(),
+    }
+}
+
+fn parse_value<T: std::str::FromStr>((line, text): (usize, String)) -> Result<T, SnapshotError> {
+    text.trim()
+        .parse()
+        .map_err(|_| malformed(line, &format!("invalid value {}", text)))
+}
+
+fn parse_optional<T: std::str::FromStr>(
+    (line, text): (usize, String),
+) -> Result<Option<T>, SnapshotError> {
+    match text.trim() {
+        "none" => Ok(None),
+        _ => parse_value((line, text)).map(Some),
+    }
+}
+
+fn parse_name<T: Copy>(
+    names: &[(T, &str)],
+    (line, text): (usize, String),
+) -> Result<T, SnapshotError> {
+    names
+        .iter()
+        .find(|(_, name)| *name == text.trim())
+        .map(|(value, _)| *value)
+        .ok_or_else(|| malformed(line, &format!("invalid value {}", text)))
+}
+
+fn parse_list<T: std::str::FromStr>(
+    (line, text): (usize, String),
+) -> Result<Vec<T>, SnapshotError> {
+    if text.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    text.split(',')
+        .map(|value| parse_value((line, value.to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::RunStatus;
+    use super::*;
+    use std::env;
+
+    // Echoes every input until it reads a 0
+    fn echo_program() -> Vec<i64> {
+        vec![3, 11, 4, 11, 1005, 11, 0, 99]
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut interpreter = Interpreter::new(echo_program()).with_memory_limit(64);
+        interpreter.add_input(4);
+        interpreter.add_input(5);
+        interpreter.execute_program().unwrap_err();
+        interpreter.add_input(6);
+
+        let snapshot = interpreter.to_snapshot();
+        assert_eq!(
+            "intcode-snapshot 2\npointer 0\nrelative_base 0\nhalt false\nhalt_on_output false\nmemory_limit 64\nexecuted 6\ninstruction_limit none\ndecoding strict\nisa day9\noverflow trap\ninput 6\noutput 4,5\nmemory 3,11,4,11,1005,11,0,99,0,0,0,5\n",
+            snapshot
+        );
+
+        let mut restored: Interpreter = Interpreter::from_snapshot(&snapshot).unwrap();
+        assert_eq!(snapshot, restored.to_snapshot());
+        assert_eq!(Ok(RunStatus::Output(6)), restored.run());
+
+        // Settings and the instruction count survive too
+        let mut interpreter = Interpreter::new(echo_program())
+            .with_instruction_limit(8)
+            .with_decode_mode(DecodeMode::Permissive)
+            .with_isa(Isa::Day5)
+            .with_overflow(Overflow::Saturate);
+        interpreter.add_input(4);
+        assert_eq!(Ok(RunStatus::Output(4)), interpreter.run());
+        let mut restored: Interpreter =
+            Interpreter::from_snapshot(&interpreter.to_snapshot()).unwrap();
+        assert_eq!(2, restored.instructions_executed());
+        assert_eq!(Some(8), restored.instruction_limit);
+        assert_eq!(DecodeMode::Permissive, restored.decoding);
+        assert_eq!(Isa::Day5, restored.isa);
+        assert_eq!(Overflow::Saturate, restored.overflow);
+        for _ in 0..10 {
+            restored.add_input(5);
+        }
+        restored.execute_program().unwrap_err();
+        assert_eq!(8, restored.instructions_executed());
+    }
+
+    #[test]
+    fn wide_words() {
+        let mut interpreter = Interpreter::from_words(vec![3i128, 5, 4, 5, 99, 0]);
+        interpreter.add_input(1 << 100);
+        interpreter.execute_program().unwrap();
+        let restored = Interpreter::<i128>::from_snapshot(&interpreter.to_snapshot()).unwrap();
+        assert_eq!(
+            vec![1 << 100],
+            restored.output.iter().cloned().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = env::temp_dir().join(format!("intcode-snapshot-{}.txt", std::process::id()));
+        let mut interpreter = Interpreter::new(echo_program());
+        interpreter.add_input(9);
+        assert_eq!(Ok(RunStatus::Output(9)), interpreter.run());
+        interpreter.save_snapshot(&path).unwrap();
+
+        let mut restored: Interpreter = Interpreter::load_snapshot(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        restored.add_input(0);
+        assert_eq!(Ok(RunStatus::Output(0)), restored.run());
+        assert_eq!(Ok(RunStatus::Halted), restored.run());
+    }
+
+    #[test]
+    fn version_1() {
+        let snapshot = "intcode-snapshot 1\npointer 2\nrelative_base 0\nhalt false\nhalt_on_output false\nmemory_limit 64\ninput 6\noutput 4,5\nmemory 3,11,4,11,1005,11,0,99,0,0,0,5\n";
+        let mut restored: Interpreter = Interpreter::from_snapshot(snapshot).unwrap();
+        assert_eq!(0, restored.instructions_executed());
+        assert_eq!(None, restored.instruction_limit);
+        assert_eq!(DecodeMode::Strict, restored.decoding);
+        assert_eq!(Some(64), restored.memory.limit);
+        assert_eq!(Ok(RunStatus::Output(5)), restored.run());
+        assert_eq!(Ok(RunStatus::Output(6)), restored.run());
+        assert_eq!(vec![4, 5], restored.get_output());
+        assert!(restored.to_snapshot().starts_with("intcode-snapshot 2\n"));
+    }
+
+    #[test]
+    fn invalid_snapshots() {
+        match Interpreter::<i64>::from_snapshot("intcode-snapshot 3\n") {
+            Err(SnapshotError::UnsupportedVersion(version)) => assert_eq!("3", version),
+            _ => panic!("expected an unsupported version"),
+        }
+        let snapshot = Interpreter::new(echo_program())
+            .to_snapshot()
+            .replace("relative_base 0", "relative_base x");
+        match Interpreter::<i64>::from_snapshot(&snapshot) {
+            Err(SnapshotError::Malformed { line, .. }) => assert_eq!(3, line),
+            _ => panic!("expected a malformed snapshot"),
+        }
+        let snapshot = Interpreter::new(echo_program())
+            .to_snapshot()
+            .replace("isa day9", "isa day10");
+        match Interpreter::<i64>::from_snapshot(&snapshot) {
+            Err(SnapshotError::Malformed { line, .. }) => assert_eq!(10, line),
+            _ => panic!("expected a malformed snapshot"),
+        }
+        assert!(Interpreter::<i64>::from_snapshot("1,2,3").is_err());
+    }
+}
@@ -0,0 +1,818 @@
+//! Symbolic execution over input ranges. The ranges and the constraints
+//! collected on them are i64 intervals, so only i64 programs can be explored.
+
+use super::{
+    to_address, DecodeMode, Fault, Instruction, Interpreter, InterpreterError, Isa, Overflow,
+    Param, RunStatus, MAX_INSTRUCTION_SIZE,
+};
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::ops::Range;
+
+/// Instructions a symbolic or verification run may execute.
+const STEP_LIMIT: u64 = 10_000_000;
+
+/// Outputs a verification run may produce.
+const OUTPUT_LIMIT: usize = 1_000_000;
+
+/// Value combinations the solver may try for the symbols it doesn't solve
+/// for directly.
+const SEARCH_LIMIT: u64 = 10_000_000;
+
+/// A value computed from symbols.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Expr {
+    Const(i64),
+    Symbol(String),
+    /// The value at an address that depends on symbols.
+    Load(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    LessThan(Box<Expr>, Box<Expr>),
+    Equals(Box<Expr>, Box<Expr>),
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Symbol(name) => write!(f, "{}", name),
+            Expr::Load(address) => write!(f, "[{}]", address),
+            Expr::Add(a, b) => write!(f, "({} + {})", a, b),
+            Expr::Mul(a, b) => write!(f, "({} * {})", a, b),
+            Expr::LessThan(a, b) => write!(f, "({} < {})", a, b),
+            Expr::Equals(a, b) => write!(f, "({} == {})", a, b),
+        }
+    }
+}
+
+/// A sum of symbols times coefficients plus a constant.
+type Linear = (i64, BTreeMap<String, i64>);
+
+impl Expr {
+    fn add(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) if a.checked_add(b).is_some() => Expr::Const(a + b),
+            (Expr::Const(0), other) | (other, Expr::Const(0)) => other,
+            (a, b) => Expr::Add(Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn mul(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) if a.checked_mul(b).is_some() => Expr::Const(a * b),
+            (Expr::Const(0), _) | (_, Expr::Const(0)) => Expr::Const(0),
+            (Expr::Const(1), other) | (other, Expr::Const(1)) => other,
+            (a, b) => Expr::Mul(Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn less_than(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const((a < b) as i64),
+            (a, b) => Expr::LessThan(Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn equals(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const((a == b) as i64),
+            (a, b) => Expr::Equals(Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn is_const(&self) -> bool {
+        matches!(self, Expr::Const(_))
+    }
+
+    /// The value of the expression, unless it reads memory through a
+    /// symbolic address, uses a symbol missing from `values` or overflows.
+    pub fn evaluate(&self, values: &BTreeMap<String, i64>) -> Option<i64> {
+        Some(match self {
+            Expr::Const(value) => *value,
+            Expr::Symbol(name) => *values.get(name)?,
+            Expr::Load(_) => return None,
+            Expr::Add(a, b) => a.evaluate(values)?.checked_add(b.evaluate(values)?)?,
+            Expr::Mul(a, b) => a.evaluate(values)?.checked_mul(b.evaluate(values)?)?,
+            Expr::LessThan(a, b) => (a.evaluate(values)? < b.evaluate(values)?) as i64,
+            Expr::Equals(a, b) => (a.evaluate(values)? == b.evaluate(values)?) as i64,
+        })
+    }
+
+    /// The expression as a sum of symbols times coefficients, or why it
+    /// can't be written as one.
+    fn linear(&self) -> Result<Linear, &'static str> {
+        const NOT_LINEAR: &str = "not linear in the symbols";
+        const OVERFLOW: &str = "a coefficient overflows";
+        match self {
+            Expr::Const(value) => Ok((*value, BTreeMap::new())),
+            Expr::Symbol(name) => Ok((0, vec![(name.clone(), 1)].into_iter().collect())),
+            Expr::Add(a, b) => {
+                let (a, mut terms) = a.linear()?;
+                let (b, others) = b.linear()?;
+                for (name, coefficient) in others {
+                    let term = terms.entry(name).or_insert(0);
+                    *term = term.checked_add(coefficient).ok_or(OVERFLOW)?;
+                }
+                terms.retain(|_, coefficient| *coefficient != 0);
+                Ok((a.checked_add(b).ok_or(OVERFLOW)?, terms))
+            }
+            Expr::Mul(a, b) => {
+                let (a, b) = (a.linear()?, b.linear()?);
+                let ((factor, _), (constant, terms)) = if a.1.is_empty() {
+                    (a, b)
+                } else if b.1.is_empty() {
+                    (b, a)
+                } else {
+                    return Err(NOT_LINEAR);
+                };
+                let terms = terms
+                    .into_iter()
+                    .map(|(name, coefficient)| {
+                        Ok((name, coefficient.checked_mul(factor).ok_or(OVERFLOW)?))
+                    })
+                    .filter(|term| {
+                        term.as_ref()
+                            .map_or(true, |(_, coefficient)| *coefficient != 0)
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok((constant.checked_mul(factor).ok_or(OVERFLOW)?, terms))
+            }
+            _ => Err(NOT_LINEAR),
+        }
+    }
+}
+
+/// A branch taken during the symbolic run: `expr` was non zero when
+/// `nonzero` is set, zero otherwise.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Constraint {
+    pub expr: Expr,
+    pub nonzero: bool,
+}
+
+impl Constraint {
+    /// Whether `values` keep the program on the same path, or `None` if that
+    /// can't be told without running it.
+    fn holds(&self, values: &BTreeMap<String, i64>) -> Option<bool> {
+        Some((self.expr.evaluate(values)? != 0) == self.nonzero)
+    }
+}
+
+/// Why a symbolic run had to stop.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SymbolicError {
+    Interpreter(InterpreterError),
+    /// An instruction word, write address, jump target or relative base
+    /// adjustment depends on a symbol.
+    SymbolicControl {
+        pointer: usize,
+    },
+    InputExhausted {
+        pointer: usize,
+    },
+    StepLimit,
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::Interpreter(err) => write!(f, "{}", err),
+            SymbolicError::SymbolicControl { pointer } => write!(
+                f,
+                "instruction at {} needs a concrete value that depends on a symbol",
+                pointer
+            ),
+            SymbolicError::InputExhausted { pointer } => {
+                write!(f, "expected input for the instruction at {}", pointer)
+            }
+            SymbolicError::StepLimit => write!(f, "step limit reached"),
+        }
+    }
+}
+
+impl error::Error for SymbolicError {}
+
+impl From<InterpreterError> for SymbolicError {
+    fn from(err: InterpreterError) -> Self {
+        SymbolicError::Interpreter(err)
+    }
+}
+
+/// Why `Symbolic::solve` gave no answer.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SolveError {
+    Run(SymbolicError),
+    /// The program halted without producing the target.
+    NoTarget,
+    /// The target isn't linear in the symbols, or no values in their ranges
+    /// satisfy it on the path the symbolic run took.
+    Unsolved {
+        expression: Expr,
+        reason: String,
+    },
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolveError::Run(err) => write!(f, "{}", err),
+            SolveError::NoTarget => write!(f, "the program doesn't produce the target"),
+            SolveError::Unsolved { expression, reason } => {
+                write!(f, "can't solve {}: {}", expression, reason)
+            }
+        }
+    }
+}
+
+impl error::Error for SolveError {}
+
+impl From<SymbolicError> for SolveError {
+    fn from(err: SymbolicError) -> Self {
+        SolveError::Run(err)
+    }
+}
+
+/// The value to solve for.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Target {
+    /// The output with this index.
+    Output(usize),
+    /// The memory cell at this address once the program halts.
+    Cell(usize),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Place {
+    Cell(usize),
+    Input(usize),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Symbol {
+    name: String,
+    range: Range<i64>,
+    place: Place,
+}
+
+/// What the program computed, in terms of its symbols.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Execution {
+    pub outputs: Vec<Expr>,
+    pub memory: Vec<Expr>,
+    pub constraints: Vec<Constraint>,
+}
+
+/// Runs a program with some memory cells or inputs replaced by symbols. The
+/// run follows the path taken with every symbol at the start of its range,
+/// recording the branches that depend on symbols as constraints. Each
+/// instruction is decoded and its concrete value computed by the
+/// `Interpreter`, with overflow trapping as `Overflow::Trap` does.
+pub struct Symbolic {
+    program: Vec<i64>,
+    inputs: Vec<Option<i64>>,
+    symbols: Vec<Symbol>,
+}
+
+/// A concrete value and the expression it was computed from.
+#[derive(Clone)]
+struct Value {
+    concrete: i64,
+    expr: Expr,
+}
+
+impl Value {
+    fn constant(value: i64) -> Self {
+        Value {
+            concrete: value,
+            expr: Expr::Const(value),
+        }
+    }
+}
+
+impl Symbolic {
+    pub fn new(program: Vec<i64>) -> Self {
+        Self {
+            program,
+            inputs: Vec::new(),
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Makes the memory cell at `address` the symbol `name`, taking values
+    /// in `range`.
+    pub fn symbolic_cell(mut self, address: usize, name: &str, range: Range<i64>) -> Self {
+        self.symbols.push(Symbol {
+            name: name.to_string(),
+            range,
+            place: Place::Cell(address),
+        });
+        self
+    }
+
+    pub fn input(mut self, value: i64) -> Self {
+        self.inputs.push(Some(value));
+        self
+    }
+
+    /// Adds the symbol `name` as the next input value.
+    pub fn symbolic_input(mut self, name: &str, range: Range<i64>) -> Self {
+        self.symbols.push(Symbol {
+            name: name.to_string(),
+            range,
+            place: Place::Input(self.inputs.len()),
+        });
+        self.inputs.push(None);
+        self
+    }
+
+    fn seed(&self) -> BTreeMap<String, i64> {
+        self.symbols
+            .iter()
+            .map(|symbol| (symbol.name.clone(), symbol.range.start))
+            .collect()
+    }
+
+    /// Runs the program with every symbol at the start of its range.
+    pub fn run(&self) -> Result<Execution, SymbolicError> {
+        let seed = self.seed();
+        let mut memory: Vec<Value> = self.program.iter().cloned().map(Value::constant).collect();
+        let mut inputs: Vec<Option<Value>> = self
+            .inputs
+            .iter()
+            .map(|input| input.map(Value::constant))
+            .collect();
+        for symbol in &self.symbols {
+            let value = Value {
+                concrete: seed[&symbol.name],
+                expr: Expr::Symbol(symbol.name.clone()),
+            };
+            match symbol.place {
+                Place::Cell(address) => {
+                    if address >= memory.len() {
+                        memory.resize(address + 1, Value::constant(0));
+                    }
+                    memory[address] = value;
+                }
+                Place::Input(index) => inputs[index] = Some(value),
+            }
+        }
+
+        let mut machine = Machine {
+            memory,
+            pointer: 0,
+            relative_base: 0,
+            outputs: Vec::new(),
+            constraints: Vec::new(),
+        };
+        let mut inputs = inputs.into_iter().flatten();
+        for _ in 0..STEP_LIMIT {
+            if !machine.step(&mut inputs)? {
+                return Ok(Execution {
+                    outputs: machine
+                        .outputs
+                        .into_iter()
+                        .map(|value| value.expr)
+                        .collect(),
+                    memory: machine.memory.into_iter().map(|value| value.expr).collect(),
+                    constraints: machine.constraints,
+                });
+            }
+        }
+        Err(SymbolicError::StepLimit)
+    }
+
+    /// Finds symbol values, within their ranges, for which `target` equals
+    /// `value`. The target must be linear in the symbols; every candidate is
+    /// checked against the path constraints and by running the program.
+    pub fn solve(&self, target: Target, value: i64) -> Result<BTreeMap<String, i64>, SolveError> {
+        let execution = self.run()?;
+        let expression = match target {
+            Target::Output(index) => execution.outputs.get(index).cloned(),
+            Target::Cell(address) => execution.memory.get(address).cloned(),
+        }
+        .ok_or(SolveError::NoTarget)?;
+        let unsolved = |reason: &str| SolveError::Unsolved {
+            expression: expression.clone(),
+            reason: reason.to_string(),
+        };
+
+        let (constant, terms) = expression.linear().map_err(unsolved)?;
+        let mut values = self.seed();
+        if terms.is_empty() {
+            return if constant == value && self.verify(&values, target, value) {
+                Ok(values)
+            } else {
+                Err(unsolved("the target doesn't depend on the symbols"))
+            };
+        }
+
+        // Solve for the symbol with the smallest coefficient, trying every
+        // value of the others
+        let (pivot, pivot_coefficient) = terms
+            .iter()
+            .min_by_key(|(_, coefficient)| coefficient.abs())
+            .map(|(name, coefficient)| (name.clone(), *coefficient))
+            .unwrap();
+        let pivot_range = self.range(&pivot);
+        let others: Vec<(&String, i64, Range<i64>)> = terms
+            .iter()
+            .filter(|(name, _)| **name != pivot)
+            .map(|(name, coefficient)| (name, *coefficient, self.range(name)))
+            .collect();
+        let combinations = others.iter().try_fold(1u64, |total, (_, _, range)| {
+            let size = range.end.checked_sub(range.start)?.max(0);
+            total.checked_mul(size as u64)
+        });
+        if combinations.is_none_or(|total| total > SEARCH_LIMIT) {
+            return Err(unsolved("too many values to try"));
+        }
+
+        // Combinations for which the arithmetic overflows can't be reached
+        // by a run that doesn't trap, so they are skipped
+        let mut overflowed = false;
+        let mut current: Vec<i64> = others.iter().map(|(_, _, range)| range.start).collect();
+        loop {
+            if others
+                .iter()
+                .zip(&current)
+                .all(|((_, _, range), value)| range.contains(value))
+            {
+                let mut rest = value.checked_sub(constant);
+                for ((name, coefficient, _), other) in others.iter().zip(&current) {
+                    rest = rest.and_then(|rest| rest.checked_sub(coefficient.checked_mul(*other)?));
+                    values.insert((*name).clone(), *other);
+                }
+                // `None` when the arithmetic overflows, `Some(None)` when
+                // there is no whole value for the pivot
+                let pivot_value = rest.and_then(|rest| {
+                    let quotient = rest.checked_div(pivot_coefficient)?;
+                    Some(Some(quotient).filter(|quotient| quotient * pivot_coefficient == rest))
+                });
+                match pivot_value {
+                    Some(Some(pivot_value)) if pivot_range.contains(&pivot_value) => {
+                        values.insert(pivot.clone(), pivot_value);
+                        let on_path = execution
+                            .constraints
+                            .iter()
+                            .all(|constraint| constraint.holds(&values) != Some(false));
+                        if on_path && self.verify(&values, target, value) {
+                            return Ok(values);
+                        }
+                    }
+                    None => overflowed = true,
+                    Some(_) => {}
+                }
+            }
+            // Next combination, like an odometer
+            let mut index = 0;
+            loop {
+                if index == current.len() {
+                    return Err(unsolved(if overflowed {
+                        "no values in range satisfy it on this path without overflowing"
+                    } else {
+                        "no values in range satisfy it on this path"
+                    }));
+                }
+                current[index] += 1;
+                if current[index] < others[index].2.end {
+                    break;
+                }
+                current[index] = others[index].2.start;
+                index += 1;
+            }
+        }
+    }
+
+    fn range(&self, name: &str) -> Range<i64> {
+        self.symbols
+            .iter()
+            .find(|symbol| symbol.name == name)
+            .map(|symbol| symbol.range.clone())
+            .unwrap()
+    }
+
+    /// Runs the program concretely with `values` for the symbols. The run
+    /// fails once it executes `STEP_LIMIT` instructions in total or produces
+    /// more than `OUTPUT_LIMIT` outputs.
+    fn verify(&self, values: &BTreeMap<String, i64>, target: Target, value: i64) -> bool {
+        let mut program = self.program.clone();
+        let mut inputs = self.inputs.clone();
+        for symbol in &self.symbols {
+            match symbol.place {
+                Place::Cell(address) => {
+                    if address >= program.len() {
+                        program.resize(address + 1, 0);
+                    }
+                    program[address] = values[&symbol.name];
+                }
+                Place::Input(index) => inputs[index] = Some(values[&symbol.name]),
+            }
+        }
+        let mut interpreter = Interpreter::new(program).with_instruction_limit(STEP_LIMIT);
+        for input in inputs.into_iter().flatten() {
+            interpreter.add_input(input);
+        }
+        let mut outputs = Vec::new();
+        loop {
+            match interpreter.run() {
+                Ok(RunStatus::Output(_)) if outputs.len() == OUTPUT_LIMIT => return false,
+                Ok(RunStatus::Output(output)) => outputs.push(output),
+                Ok(RunStatus::Halted) => break,
+                _ => return false,
+            }
+        }
+        match target {
+            Target::Output(index) => outputs.get(index) == Some(&value),
+            Target::Cell(address) => interpreter.memory.get(address as i64) == value,
+        }
+    }
+}
+
+struct Machine {
+    memory: Vec<Value>,
+    pointer: usize,
+    relative_base: i64,
+    outputs: Vec<Value>,
+    constraints: Vec<Constraint>,
+}
+
+impl Machine {
+    fn cell(&self, address: usize) -> Value {
+        self.memory
+            .get(address)
+            .cloned()
+            .unwrap_or_else(|| Value::constant(0))
+    }
+
+    /// The word of the current instruction holding parameter `index`.
+    fn word(&self, index: usize) -> Value {
+        self.cell(self.pointer + 1 + index)
+    }
+
+    fn address(&self, param: &Param, index: usize) -> Result<Value, SymbolicError> {
+        let word = self.word(index);
+        let (concrete, expr) = match param {
+            Param::Position(_) => (Ok(word.concrete), word.expr),
+            Param::Relative(_) => (
+                self.relative_base
+                    .checked_add(word.concrete)
+                    .ok_or(Fault::Overflow),
+                Expr::add(Expr::Const(self.relative_base), word.expr),
+            ),
+            Param::Value(_) => unreachable!("immediate parameters have no address"),
+        };
+        let concrete = concrete
+            .and_then(to_address)
+            .map_err(|fault| self.fault(fault))?;
+        Ok(Value {
+            concrete: concrete as i64,
+            expr,
+        })
+    }
+
+    /// The interpreter error for `fault` in the current instruction.
+    fn fault(&self, fault: Fault<i64>) -> SymbolicError {
+        fault
+            .at(self.pointer, self.cell(self.pointer).concrete)
+            .into()
+    }
+
+    fn read(&self, param: &Param, index: usize) -> Result<Value, SymbolicError> {
+        if let Param::Value(_) = param {
+            return Ok(self.word(index));
+        }
+        let address = self.address(param, index)?;
+        let cell = self.cell(address.concrete as usize);
+        if address.expr.is_const() {
+            Ok(cell)
+        } else {
+            Ok(Value {
+                concrete: cell.concrete,
+                expr: Expr::Load(Box::new(address.expr)),
+            })
+        }
+    }
+
+    fn write(&mut self, param: &Param, index: usize, value: Value) -> Result<(), SymbolicError> {
+        let address = self.address(param, index)?;
+        if !address.expr.is_const() {
+            return Err(self.symbolic_control());
+        }
+        let address = address.concrete as usize;
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, Value::constant(0));
+        }
+        self.memory[address] = value;
+        Ok(())
+    }
+
+    fn symbolic_control(&self) -> SymbolicError {
+        SymbolicError::SymbolicControl {
+            pointer: self.pointer,
+        }
+    }
+
+    /// Executes one instruction, returning false once the program halts.
+    fn step(&mut self, inputs: &mut impl Iterator<Item = Value>) -> Result<bool, SymbolicError> {
+        let words: Vec<Value> = (0..MAX_INSTRUCTION_SIZE)
+            .map(|i| self.cell(self.pointer + i))
+            .collect();
+        if !words[0].expr.is_const() {
+            return Err(self.symbolic_control());
+        }
+        let concrete: Vec<i64> = words.iter().map(|word| word.concrete).collect();
+        let instruction = Interpreter::decode(&concrete, 0, DecodeMode::Strict, Isa::Day9)
+            .map_err(|err| match err {
+                InterpreterError::UnknownOpcode { opcode, .. } => InterpreterError::UnknownOpcode {
+                    pointer: self.pointer,
+                    opcode,
+                },
+                InterpreterError::InvalidMode {
+                    opcode,
+                    param,
+                    mode,
+                    ..
+                } => InterpreterError::InvalidMode {
+                    pointer: self.pointer,
+                    opcode,
+                    param,
+                    mode,
+                },
+                InterpreterError::ImmediateWrite { opcode, param, .. } => {
+                    InterpreterError::ImmediateWrite {
+                        pointer: self.pointer,
+                        opcode,
+                        param,
+                    }
+                }
+                err => err,
+            })?;
+
+        let mut next = self.pointer + instruction.instruction_size();
+        match &instruction {
+            Instruction::Add(a, b, c)
+            | Instruction::Mul(a, b, c)
+            | Instruction::LessThan(a, b, c)
+            | Instruction::Equals(a, b, c) => {
+                let (a, b) = (self.read(a, 0)?, self.read(b, 1)?);
+                let concrete = instruction
+                    .combine(Overflow::Trap, &a.concrete, &b.concrete)
+                    .map_err(|fault| self.fault(fault))?;
+                let expr = match instruction {
+                    Instruction::Add(_, _, _) => Expr::add(a.expr, b.expr),
+                    Instruction::Mul(_, _, _) => Expr::mul(a.expr, b.expr),
+                    Instruction::LessThan(_, _, _) => Expr::less_than(a.expr, b.expr),
+                    _ => Expr::equals(a.expr, b.expr),
+                };
+                self.write(c, 2, Value { concrete, expr })?;
+            }
+            Instruction::Inp(a) => {
+                let input = inputs.next().ok_or(SymbolicError::InputExhausted {
+                    pointer: self.pointer,
+                })?;
+                self.write(a, 0, input)?;
+            }
+            Instruction::Out(a) => {
+                let value = self.read(a, 0)?;
+                self.outputs.push(value);
+            }
+            Instruction::JumpTrue(a, b) | Instruction::JumpFalse(a, b) => {
+                let condition = self.read(a, 0)?;
+                let jumps = instruction.jumps(&condition.concrete);
+                if !condition.expr.is_const() {
+                    self.constraints.push(Constraint {
+                        expr: condition.expr,
+                        nonzero: condition.concrete != 0,
+                    });
+                }
+                if jumps {
+                    let target = self.read(b, 1)?;
+                    if !target.expr.is_const() {
+                        return Err(self.symbolic_control());
+                    }
+                    next = to_address(target.concrete).map_err(|fault| self.fault(fault))?;
+                }
+            }
+            Instruction::AdjustBase(a) => {
+                let offset = self.read(a, 0)?;
+                if !offset.expr.is_const() {
+                    return Err(self.symbolic_control());
+                }
+                self.relative_base = Overflow::Trap
+                    .add(&self.relative_base, &offset.concrete)
+                    .map_err(|fault| self.fault(fault))?;
+            }
+            Instruction::Halt => return Ok(false),
+            Instruction::Extension { .. } => unreachable!("extensions are not decoded here"),
+        }
+        self.pointer = next;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Reads [noun] and [verb] like the first instruction of day 2, then
+    // computes 3 * noun + verb + 5 in cell 0
+    fn program() -> Vec<i64> {
+        vec![
+            1, 0, 0, 3, 1002, 1, 3, 20, 1, 20, 2, 0, 1001, 0, 5, 0, 99, 0, 0, 0, 0,
+        ]
+    }
+
+    #[test]
+    fn linear_target() {
+        let symbolic = Symbolic::new(program())
+            .symbolic_cell(1, "noun", 0..10)
+            .symbolic_cell(2, "verb", 0..10);
+        let execution = symbolic.run().unwrap();
+        assert_eq!("(((noun * 3) + verb) + 5)", execution.memory[0].to_string());
+        assert_eq!("([noun] + [verb])", execution.memory[3].to_string());
+
+        let solution = symbolic.solve(Target::Cell(0), 30).unwrap();
+        assert_eq!(Some(&6), solution.get("noun"));
+        assert_eq!(Some(&7), solution.get("verb"));
+        assert!(symbolic.solve(Target::Cell(0), 1000).is_err());
+    }
+
+    #[test]
+    fn unsolvable_targets() {
+        // Outputs noun * verb
+        let symbolic = Symbolic::new(vec![2, 7, 8, 9, 4, 9, 99, 0, 0, 0])
+            .symbolic_cell(7, "noun", 0..10)
+            .symbolic_cell(8, "verb", 0..10);
+        match symbolic.solve(Target::Output(0), 12) {
+            Err(SolveError::Unsolved { expression, .. }) => {
+                assert_eq!("(noun * verb)", expression.to_string())
+            }
+            result => panic!("expected no solution, got {:?}", result),
+        }
+
+        let symbolic = Symbolic::new(vec![1105, 1, 5]).symbolic_cell(2, "target", 0..10);
+        assert_eq!(
+            Err(SolveError::Run(SymbolicError::SymbolicControl {
+                pointer: 0
+            })),
+            symbolic.solve(Target::Output(0), 0)
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        // Adds [5] and [6], which overflows once x is 1
+        let symbolic =
+            Symbolic::new(vec![1, 5, 6, 0, 99, i64::MAX, 0]).symbolic_cell(6, "x", 1..10);
+        assert_eq!(
+            Err(SymbolicError::Interpreter(InterpreterError::Overflow {
+                pointer: 0,
+                opcode: 1
+            })),
+            symbolic.run()
+        );
+
+        // Computes x * MAX + x * MAX, whose coefficient doesn't fit
+        let symbolic = Symbolic::new(vec![1002, 9, i64::MAX, 10, 1, 10, 10, 0, 99, 0, 0])
+            .symbolic_cell(9, "x", 0..2);
+        match symbolic.solve(Target::Cell(0), 0) {
+            Err(SolveError::Unsolved { reason, .. }) => {
+                assert_eq!("a coefficient overflows", reason)
+            }
+            result => panic!("expected no solution, got {:?}", result),
+        }
+
+        let sum = Expr::add(Expr::Const(i64::MAX), Expr::Symbol("x".to_string()));
+        let values = vec![("x".to_string(), 1)].into_iter().collect();
+        assert_eq!(None, sum.evaluate(&values));
+    }
+
+    #[test]
+    fn path_constraints() {
+        // Outputs x + 1 when x < 10, and x * 2 otherwise
+        let program = vec![
+            3, 100, 1007, 100, 10, 101, 1006, 101, 16, 1001, 100, 1, 102, 4, 102, 99, 1002, 100, 2,
+            102, 1105, 1, 13,
+        ];
+        let symbolic = Symbolic::new(program).symbolic_input("x", 0..100);
+        let execution = symbolic.run().unwrap();
+        assert_eq!(
+            vec![Constraint {
+                expr: Expr::LessThan(
+                    Box::new(Expr::Symbol("x".to_string())),
+                    Box::new(Expr::Const(10))
+                ),
+                nonzero: true
+            }],
+            execution.constraints
+        );
+        assert_eq!(
+            Some(&5),
+            symbolic.solve(Target::Output(0), 6).unwrap().get("x")
+        );
+        // 50 is only reached through the other branch
+        assert!(symbolic.solve(Target::Output(0), 50).is_err());
+    }
+}
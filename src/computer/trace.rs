@@ -0,0 +1,215 @@
+use super::{Access, Instruction, Memory, Param, Word};
+use std::io::{self, Write};
+
+/// How trace records are written: one human readable line, or one JSON
+/// object per line.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TraceFormat {
+    Text,
+    JsonLines,
+}
+
+/// What a single executed instruction did.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TraceEntry<W = i64> {
+    pub step: u64,
+    pub pointer: usize,
+    pub opcode: W,
+    pub instruction: String,
+    /// The value read for each parameter, or the address written to for
+    /// the parameter the instruction writes.
+    pub operands: Vec<W>,
+    pub writes: Vec<Access<W>>,
+}
+
+impl<W: Word> TraceEntry<W> {
+    pub fn to_text(&self) -> String {
+        let operands: Vec<String> = self.operands.iter().map(|o| o.to_string()).collect();
+        let mut line = format!(
+            "{:>8} {:>5}: {:<24} [{}]",
+            self.step,
+            self.pointer,
+            self.instruction,
+            operands.join(", ")
+        );
+        for write in &self.writes {
+            if let Access::Write { address, old, new } = write {
+                line.push_str(&format!(" {}: {} -> {}", address, old, new));
+            }
+        }
+        line
+    }
+
+    pub fn to_json(&self) -> String {
+        let operands: Vec<String> = self.operands.iter().map(|o| o.to_string()).collect();
+        let writes: Vec<String> = self
+            .writes
+            .iter()
+            .filter_map(|write| match write {
+                Access::Write { address, old, new } => Some(format!(
+                    "{{\"address\":{},\"old\":{},\"new\":{}}}",
+                    address, old, new
+                )),
+                Access::Read { .. } => None,
+            })
+            .collect();
+        format!(
+            "{{\"step\":{},\"pointer\":{},\"opcode\":{},\"instruction\":\"{}\",\"operands\":[{}],\"writes\":[{}]}}",
+            self.step,
+            self.pointer,
+            self.opcode,
+            escape(&self.instruction),
+            operands.join(","),
+            writes.join(",")
+        )
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub(super) struct Tracer {
+    writer: Box<dyn Write + Send>,
+    format: TraceFormat,
+    steps: u64,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub(super) fn new(writer: Box<dyn Write + Send>, format: TraceFormat) -> Self {
+        Self {
+            writer,
+            format,
+            steps: 0,
+            error: None,
+        }
+    }
+
+    pub(super) fn record<W: Word>(
+        &mut self,
+        pointer: usize,
+        opcode: &W,
+        instruction: &Instruction<W>,
+        operands: Vec<W>,
+        accesses: &[Access<W>],
+    ) {
+        let entry = TraceEntry {
+            step: self.steps,
+            pointer,
+            opcode: opcode.clone(),
+            instruction: instruction.to_string(),
+            operands,
+            writes: accesses
+                .iter()
+                .filter(|access| matches!(access, Access::Write { .. }))
+                .cloned()
+                .collect(),
+        };
+        self.steps += 1;
+
+        // Stop writing after the first error, it is reported by `finish`
+        if self.error.is_none() {
+            let line = match self.format {
+                TraceFormat::Text => entry.to_text(),
+                TraceFormat::JsonLines => entry.to_json(),
+            };
+            if let Err(err) = writeln!(self.writer, "{}", line) {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    pub(super) fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+impl<W: Word> Instruction<W> {
+    /// Resolves every parameter against the current machine state, without
+    /// logging the reads.
+    pub(super) fn operands(&self, memory: &Memory<W>, relative_base: &W) -> Vec<W> {
+        self.params()
+            .iter()
+            .enumerate()
+            .map(|(index, param)| {
+                let write = self.writes(index);
+                match param {
+                    Param::Value(value) => value.clone(),
+                    Param::Position(address) if write => address.clone(),
+                    Param::Position(address) => memory.get(address.clone()),
+                    Param::Relative(offset) if write => relative_base.wrapping_add(offset),
+                    Param::Relative(offset) => memory.get(relative_base.wrapping_add(offset)),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Interpreter;
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(|line| line.to_string())
+                .collect()
+        }
+    }
+
+    #[test]
+    fn json_lines() {
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::new(vec![1002, 4, 3, 4, 33])
+            .with_trace(buffer.clone(), TraceFormat::JsonLines);
+        interpreter.execute_program().unwrap();
+        interpreter.finish_trace().unwrap();
+        assert_eq!(
+            vec![
+                "{\"step\":0,\"pointer\":0,\"opcode\":1002,\"instruction\":\"MUL 4, #3, 4\",\"operands\":[33,3,4],\"writes\":[{\"address\":4,\"old\":33,\"new\":99}]}",
+                "{\"step\":1,\"pointer\":4,\"opcode\":99,\"instruction\":\"HLT\",\"operands\":[],\"writes\":[]}",
+            ],
+            buffer.lines()
+        );
+    }
+
+    #[test]
+    fn text() {
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::new(vec![109, 3, 203, 4, 204, 4, 99])
+            .with_trace(buffer.clone(), TraceFormat::Text);
+        interpreter.add_input(8);
+        interpreter.execute_program().unwrap();
+        interpreter.finish_trace().unwrap();
+        assert_eq!(
+            vec![
+                "       0     0: ARB #3                   [3]",
+                "       1     2: IN @4                    [7] 7: 0 -> 8",
+                "       2     4: OUT @4                   [8]",
+                "       3     6: HLT                      []",
+            ],
+            buffer.lines()
+        );
+    }
+}
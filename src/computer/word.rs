@@ -0,0 +1,177 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// A value held in a memory cell of an `Interpreter`. Implemented for `i64`,
+/// `i128`, and `num_bigint::BigInt` with the `bigint` feature.
+pub trait Word: Clone + Ord + fmt::Debug + fmt::Display + FromStr + Send + 'static {
+    fn from_i64(value: i64) -> Self;
+
+    /// The value as an `i64`, if it fits.
+    fn to_i64(&self) -> Option<i64>;
+
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+    fn saturating_add(&self, other: &Self) -> Self;
+    fn saturating_mul(&self, other: &Self) -> Self;
+
+    /// Bits mixed into memory hashes by loop detection. Equal words give
+    /// equal bits, and 0 gives 0 while no other word does.
+    fn hash_bits(&self) -> u64;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::from_i64(0)
+    }
+}
+
+macro_rules! primitive_word {
+    ($type:ty, $value:ident => $hash_bits:expr) => {
+        impl Word for $type {
+            fn from_i64(value: i64) -> Self {
+                value.into()
+            }
+
+            fn to_i64(&self) -> Option<i64> {
+                i64::try_from(*self).ok()
+            }
+
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                <$type>::checked_add(*self, *other)
+            }
+
+            fn checked_mul(&self, other: &Self) -> Option<Self> {
+                <$type>::checked_mul(*self, *other)
+            }
+
+            fn wrapping_add(&self, other: &Self) -> Self {
+                <$type>::wrapping_add(*self, *other)
+            }
+
+            fn wrapping_mul(&self, other: &Self) -> Self {
+                <$type>::wrapping_mul(*self, *other)
+            }
+
+            fn saturating_add(&self, other: &Self) -> Self {
+                <$type>::saturating_add(*self, *other)
+            }
+
+            fn saturating_mul(&self, other: &Self) -> Self {
+                <$type>::saturating_mul(*self, *other)
+            }
+
+            fn hash_bits(&self) -> u64 {
+                let $value = *self;
+                $hash_bits
+            }
+        }
+    };
+}
+
+primitive_word!(i64, value => value as u64);
+primitive_word!(i128, value => match value {
+    0 => 0,
+    _ => fold_bits(value as u64, (value >> 64) as u64),
+});
+
+/// Hash bits of a nonzero word from two halves of its bit pattern. The high
+/// half is scrambled first, so that sign extension, as in -1, doesn't cancel
+/// out the low half.
+fn fold_bits(low: u64, high: u64) -> u64 {
+    (low ^ high.wrapping_mul(0x9e37_79b9_7f4a_7c15).rotate_left(32)).max(1)
+}
+
+/// Never overflows, so every overflow policy gives the exact result.
+#[cfg(feature = "bigint")]
+impl Word for num_bigint::BigInt {
+    fn from_i64(value: i64) -> Self {
+        value.into()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        i64::try_from(self).ok()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+
+    fn saturating_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn saturating_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+
+    fn hash_bits(&self) -> u64 {
+        let bits = self
+            .iter_u64_digits()
+            .fold(0u64, |bits, digit| fold_bits(digit, bits));
+        match self.sign() {
+            num_bigint::Sign::NoSign => 0,
+            num_bigint::Sign::Minus => fold_bits(bits, 1),
+            num_bigint::Sign::Plus => bits,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Interpreter;
+    use super::*;
+
+    // Squares its input twice and outputs the result
+    fn squares<W: Word>(input: W) -> Vec<W> {
+        let program = vec![3, 13, 2, 13, 13, 13, 2, 13, 13, 13, 4, 13, 99, 0];
+        let program = program.into_iter().map(W::from_i64).collect();
+        let mut interpreter = Interpreter::from_words(program);
+        interpreter.add_input(input);
+        interpreter.execute_program().unwrap();
+        interpreter.get_output()
+    }
+
+    #[test]
+    fn wide_words() {
+        assert_eq!(vec![1 << 80], squares(1i128 << 20));
+        assert_eq!(i128::from(i64::MAX).to_i64(), Some(i64::MAX));
+        assert_eq!(None, (i128::from(i64::MAX) + 1).to_i64());
+    }
+
+    #[test]
+    fn hash_bits() {
+        assert_eq!(0, 0i128.hash_bits());
+        let words = [1, -1, i128::MIN, i128::MAX, 1 << 64, -(1 << 64)];
+        for (index, word) in words.iter().enumerate() {
+            assert_ne!(0, word.hash_bits());
+            for other in &words[index + 1..] {
+                assert_ne!(word.hash_bits(), other.hash_bits());
+            }
+        }
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn big_words() {
+        use num_bigint::BigInt;
+        let input = BigInt::from(1) << 40;
+        assert_eq!(vec![BigInt::from(1) << 160], squares(input));
+        for word in &[BigInt::from(-1), -BigInt::from(u64::MAX)] {
+            assert_ne!(0, word.hash_bits());
+            assert_ne!((-word).hash_bits(), word.hash_bits());
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use crate::computer::symbolic::{Symbolic, Target};
+use crate::computer::{Interpreter, InterpreterError, Isa};
 use std::fs;
 
 pub fn solve1() -> i32 {
@@ -9,6 +11,19 @@ pub fn solve1() -> i32 {
 
 pub fn solve2() -> i32 {
     let original_input = parse_input();
+    let program = original_input.iter().map(|&word| i64::from(word)).collect();
+    let symbolic = Symbolic::new(program)
+        .symbolic_cell(1, "noun", 0..1000)
+        .symbolic_cell(2, "verb", 0..1000);
+    match symbolic.solve(Target::Cell(0), 19690720) {
+        Ok(values) => {
+            let (i, j) = (values["noun"] as i32, values["verb"] as i32);
+            println!("terms: {} {}", i, j);
+            return 100 * i + j;
+        }
+        Err(err) => println!("{}, searching instead", err),
+    }
+
     let mut result = 0i32;
     for i in 0..1000 {
         for j in 0..1000 {
@@ -26,7 +41,27 @@ pub fn solve2() -> i32 {
     result
 }
 
-fn process_program(program: &mut Vec<i32>) -> Result<i32, &str> {
+/// Runs `program` with the day 2 instruction set, leaving the final memory
+/// in it and returning cell 0. The program can't grow, and addresses past
+/// its end read as 0.
+pub(crate) fn process_program(program: &mut [i32]) -> Result<i32, InterpreterError> {
+    let mut interpreter = Interpreter::new(program.iter().map(|&word| i64::from(word)).collect())
+        .with_isa(Isa::Day2)
+        .with_memory_limit(program.len());
+    interpreter.execute_program()?;
+    for (word, &value) in program.iter_mut().zip(interpreter.memory()) {
+        // Values are computed as i64 and an overflow there fails the run, but
+        // results past the i32 range are truncated here
+        *word = value as i32;
+    }
+    Ok(program[0])
+}
+
+/// The day 2 engine as it was before `process_program` ran on the
+/// `Interpreter`, kept as the oracle `fuzz::compare` checks it against.
+/// Unlike the `Interpreter` it fails on any address past the end, and its
+/// i32 arithmetic overflows.
+pub(crate) fn reference_program(program: &mut [i32]) -> Result<i32, &'static str> {
     let mut i = 0;
     while program[i] != 99 {
         let pos1 = program[i + 1] as usize;
@@ -60,11 +95,62 @@ mod test {
     #[test]
     fn part1() {
         assert_eq!(
-            process_program(&mut vec![1, 1, 1, 4, 99, 5, 6, 0, 99]).unwrap(),
+            process_program(&mut [1, 1, 1, 4, 99, 5, 6, 0, 99]).unwrap(),
             30
         );
-        assert_eq!(process_program(&mut vec![1, 0, 0, 0, 99]).unwrap(), 2);
-        assert_eq!(process_program(&mut vec![2, 3, 0, 3, 99]).unwrap(), 2);
-        assert_eq!(process_program(&mut vec![2, 4, 4, 5, 99, 0]).unwrap(), 2);
+        assert_eq!(process_program(&mut [1, 0, 0, 0, 99]).unwrap(), 2);
+        assert_eq!(process_program(&mut [2, 3, 0, 3, 99]).unwrap(), 2);
+        assert_eq!(process_program(&mut [2, 4, 4, 5, 99, 0]).unwrap(), 2);
+    }
+
+    #[test]
+    fn reads_past_the_end() {
+        // Read as 0, where the reference engine fails
+        let mut program = [1, 0, 7, 0, 99];
+        assert_eq!(Ok(1), process_program(&mut program));
+        assert_eq!([1, 0, 7, 0, 99], program);
+        assert_eq!(
+            Err("no valid program"),
+            reference_program(&mut [1, 0, 7, 0, 99])
+        );
+    }
+
+    #[test]
+    fn reference() {
+        let mut program = [1, 1, 1, 4, 99, 5, 6, 0, 99];
+        assert_eq!(Ok(30), reference_program(&mut program));
+        assert_eq!([30, 1, 1, 4, 2, 5, 6, 0, 99], program);
+        assert_eq!(
+            Err("no valid program"),
+            reference_program(&mut [1, 0, 0, 5, 99])
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            process_program(&mut [4, 0, 99]),
+            Err(InterpreterError::UnknownOpcode {
+                pointer: 0,
+                opcode: 4
+            })
+        );
+        assert_eq!(
+            process_program(&mut [1101, 1, 1, 0, 99]),
+            Err(InterpreterError::InvalidMode {
+                pointer: 0,
+                opcode: 1101,
+                param: 1,
+                mode: 1
+            })
+        );
+        assert_eq!(
+            process_program(&mut [1, 0, 0, 5, 99]),
+            Err(InterpreterError::AddressOutOfRange {
+                pointer: 0,
+                opcode: 1,
+                address: 5
+            })
+        );
     }
 }
@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fs;
 
@@ -60,7 +59,7 @@ impl Move {
             .collect()
     }
 
-    fn to_nodes(&self, current_coord: &Coor, mut current_num_step: i32) -> Vec<Node> {
+    fn to_nodes(&self, current_coord: &Coor, current_num_step: i32) -> Vec<Node> {
         (1..=self.steps)
             .map(|step| match self.direction {
                 Direction::R => Node {
@@ -110,8 +109,8 @@ pub fn solve2() -> i32 {
     wire1
         .intersection(&wire2)
         .map(|inter| {
-            let steps1 = wire1.get(&inter).unwrap().steps;
-            let steps2 = wire2.get(&inter).unwrap().steps;
+            let steps1 = wire1.get(inter).unwrap().steps;
+            let steps2 = wire2.get(inter).unwrap().steps;
 
             steps1 + steps2
         })
@@ -123,10 +122,10 @@ fn dist_to_origin(coor: &Coor) -> i32 {
     coor.0.abs() + coor.1.abs()
 }
 
-fn wire(moves: &Vec<Move>) -> HashSet<Coor> {
+fn wire(moves: &[Move]) -> HashSet<Coor> {
     let mut current_coor = Coor(0, 0);
     moves
-        .into_iter()
+        .iter()
         .flat_map(|m| {
             let coors = m.to_coord(&current_coor);
             let last_coor = coors.last().expect("last coor should exist");
@@ -136,11 +135,11 @@ fn wire(moves: &Vec<Move>) -> HashSet<Coor> {
         .collect()
 }
 
-fn wire2(moves: &Vec<Move>) -> HashSet<Node> {
+fn wire2(moves: &[Move]) -> HashSet<Node> {
     let mut current_coor = Coor(0, 0);
     let mut current_step = 0;
     moves
-        .into_iter()
+        .iter()
         .flat_map(|m| {
             println!("current Step {}", current_step);
             let nodes = m.to_nodes(&current_coor, current_step);
@@ -156,11 +155,7 @@ fn parse_input() -> Vec<Vec<Move>> {
     fs::read_to_string("./input/day3_1.txt")
         .unwrap()
         .lines()
-        .map(|line| {
-            line.split(',')
-                .map(|str_dir| Move::new(str_dir))
-                .collect::<Vec<_>>()
-        })
+        .map(|line| line.split(',').map(Move::new).collect::<Vec<_>>())
         .take(2)
         .collect()
 }
@@ -1,11 +1,12 @@
 use crate::computer::Interpreter;
+use crate::computer::{cfg, disassembler};
 use std::fs;
 
 pub fn solve1() {
     let program = parse_input();
     let mut interpreter = Interpreter::new(program);
     interpreter.add_input(1);
-    interpreter.execute_program();
+    interpreter.execute_program().unwrap();
     println!("{:?}", interpreter.get_output());
 }
 
@@ -13,10 +14,18 @@ pub fn solve2() {
     let program = parse_input();
     let mut interpreter = Interpreter::new(program);
     interpreter.add_input(5);
-    interpreter.execute_program();
+    interpreter.execute_program().unwrap();
     println!("{:?}", interpreter.get_output());
 }
 
+pub fn disassemble() {
+    print!("{}", disassembler::listing(&parse_input()));
+}
+
+pub fn control_flow_graph() {
+    print!("{}", cfg::build(&parse_input()).to_dot());
+}
+
 fn parse_input() -> Vec<i64> {
     fs::read_to_string("./input/day5_1.txt")
         .unwrap()
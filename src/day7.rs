@@ -1,3 +1,4 @@
+use crate::computer::runtime::Runtime;
 use crate::computer::Interpreter;
 use permutohedron::Heap;
 use std::fs;
@@ -29,42 +30,45 @@ pub fn solve1() {
     );
 }
 
-fn run_for_permutation(original_program: &Vec<i64>, phases: Vec<i64>) -> i64 {
+fn run_for_permutation(original_program: &[i64], phases: Vec<i64>) -> i64 {
     let mut input = 0;
     for phase in phases {
-        input = run_step(original_program.clone(), phase, input);
+        input = run_step(original_program.to_vec(), phase, input);
     }
     input
 }
 
-fn run_loop_for_permutation(original_program: &Vec<i64>, phases: Vec<i64>) -> i64 {
-    let mut interpreters: Vec<Interpreter> = phases
+fn run_loop_for_permutation(original_program: &[i64], phases: Vec<i64>) -> i64 {
+    let mut runtime = Runtime::new();
+    let amplifiers: Vec<usize> = phases
         .iter()
-        .map(|phase| {
-            let mut interpreter = Interpreter::new(original_program.clone()).halt_on_output();
+        .enumerate()
+        .map(|(index, phase)| {
+            let mut interpreter = Interpreter::new(original_program.to_vec());
             interpreter.add_input(*phase);
-            interpreter
+            if index == 0 {
+                interpreter.add_input(0);
+            }
+            runtime.add(interpreter)
         })
         .collect();
-
-    let mut output = 0;
-    for index in 0.. {
-        let index = index % 5;
-        interpreters[index].add_input(output);
-        interpreters[index].execute_program();
-        match interpreters[index].output.pop_back() {
-            Some(o) => output = o,
-            None => break,
-        }
+    for (index, amplifier) in amplifiers.iter().enumerate() {
+        runtime.connect(*amplifier, amplifiers[(index + 1) % amplifiers.len()]);
     }
-    output
+
+    // The first amplifier halts before reading the last signal sent to it
+    let reports = runtime.run();
+    *reports[amplifiers[0]]
+        .unread
+        .last()
+        .expect("output expected")
 }
 
 fn run_step(program: Vec<i64>, phase: i64, input: i64) -> i64 {
     let mut interpreter = Interpreter::new(program);
     interpreter.add_input(phase);
     interpreter.add_input(input);
-    interpreter.execute_program();
+    interpreter.execute_program().unwrap();
     *interpreter.get_output().last().expect("output expected")
 }
 
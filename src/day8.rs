@@ -8,7 +8,7 @@ pub fn solve1() {
 
     let result = layers
         .into_iter()
-        .min_by_key(|layer| count_by_digit(&layer, 0))
+        .min_by_key(|layer| count_by_digit(layer, 0))
         .map(|layer| count_by_digit(&layer, 1) * count_by_digit(&layer, 2))
         .unwrap();
 
@@ -0,0 +1,420 @@
+//! Differential fuzzing of `computer::Interpreter` against the original day 2
+//! engine.
+//!
+//! Random straight-line add/mul programs are encoded twice: with parameter
+//! modes for the `Interpreter`, after setting the relative base, and with
+//! every operand in position mode for `day2::reference_program`, the day 2
+//! loop written before the `Interpreter` existed. Both must leave the same
+//! values in the data cells.
+
+use crate::computer::{Interpreter, InterpreterError};
+use crate::day2;
+use std::fmt;
+
+/// Data cells every program works on.
+const DATA_CELLS: usize = 6;
+
+/// Longest program generated, in instructions.
+const MAX_OPS: usize = 12;
+
+/// Largest relative base generated. Bases past the data cells give negative
+/// relative offsets.
+const MAX_BASE: u64 = 80;
+
+/// A small xorshift generator, so that runs can be repeated from a seed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..bound`.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+/// Where an instruction reads or writes. `Position` and `Relative` name a
+/// data cell, encoded as its address or as its offset from the relative
+/// base. The base is never 0, so the two encodings always differ.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Operand {
+    Position(usize),
+    Relative(usize),
+    Immediate(i64),
+}
+
+impl Operand {
+    fn mode(self) -> i64 {
+        match self {
+            Operand::Position(_) => 0,
+            Operand::Immediate(_) => 1,
+            Operand::Relative(_) => 2,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Op {
+    pub multiply: bool,
+    pub a: Operand,
+    pub b: Operand,
+    pub destination: Operand,
+}
+
+/// A program: the relative base, the instructions, and the initial value of
+/// the data cells.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Case {
+    pub base: i64,
+    pub ops: Vec<Op>,
+    pub data: Vec<i64>,
+}
+
+impl Case {
+    pub fn random(rng: &mut Rng) -> Self {
+        let data: Vec<i64> = (0..DATA_CELLS).map(|_| rng.below(20) as i64).collect();
+        let mut case = Case {
+            base: 1 + rng.below(MAX_BASE) as i64,
+            ops: Vec::new(),
+            data,
+        };
+        let count = 1 + rng.below(MAX_OPS as u64) as usize;
+        // Ops that would overflow the day 2 engine's i32 are retried
+        for _ in 0..count * 10 {
+            if case.ops.len() == count {
+                break;
+            }
+            case.ops.push(random_op(rng));
+            if case.expected().is_none() {
+                case.ops.pop();
+            }
+        }
+        case
+    }
+
+    /// Where the data cells start in `intcode`: right after the base
+    /// adjustment, the instructions and the final halt.
+    fn data_start(&self) -> usize {
+        2 + self.day2_data_start()
+    }
+
+    /// Where the data cells start in `day2_program`.
+    fn day2_data_start(&self) -> usize {
+        self.ops.len() * 4 + 1
+    }
+
+    /// The program for the `Interpreter`, with parameter modes, starting by
+    /// setting the relative base.
+    pub fn intcode(&self) -> Vec<i64> {
+        let start = self.data_start();
+        let word = |operand: Operand| match operand {
+            Operand::Position(cell) => (start + cell) as i64,
+            Operand::Relative(cell) => (start + cell) as i64 - self.base,
+            Operand::Immediate(value) => value,
+        };
+        let mut program = vec![109, self.base];
+        for op in &self.ops {
+            let opcode = if op.multiply { 2 } else { 1 };
+            program.push(
+                opcode + op.a.mode() * 100 + op.b.mode() * 1000 + op.destination.mode() * 10000,
+            );
+            program.extend(vec![word(op.a), word(op.b), word(op.destination)]);
+        }
+        program.push(99);
+        program.extend(&self.data);
+        program
+    }
+
+    /// The program for `day2::reference_program`: immediates become cells
+    /// after the data cells.
+    pub fn day2_program(&self) -> Vec<i32> {
+        let start = self.day2_data_start();
+        let mut constants = Vec::new();
+        let mut address = |operand: Operand| match operand {
+            Operand::Position(cell) | Operand::Relative(cell) => (start + cell) as i32,
+            Operand::Immediate(value) => {
+                constants.push(value as i32);
+                (start + DATA_CELLS + constants.len() - 1) as i32
+            }
+        };
+        let mut program = Vec::new();
+        for op in &self.ops {
+            let opcode = if op.multiply { 2 } else { 1 };
+            let words = [address(op.a), address(op.b), address(op.destination)];
+            program.push(opcode);
+            program.extend(&words);
+        }
+        program.push(99);
+        program.extend(self.data.iter().map(|value| *value as i32));
+        program.extend(constants);
+        program
+    }
+
+    /// The final data cells, or `None` if a value doesn't fit in an i32.
+    fn expected(&self) -> Option<Vec<i64>> {
+        let mut data = self.data.clone();
+        for op in &self.ops {
+            let value = |operand: Operand, data: &[i64]| match operand {
+                Operand::Position(cell) | Operand::Relative(cell) => data[cell],
+                Operand::Immediate(value) => value,
+            };
+            let (a, b) = (value(op.a, &data), value(op.b, &data));
+            let result = if op.multiply { a * b } else { a + b };
+            if result < i64::from(i32::MIN) || result > i64::from(i32::MAX) {
+                return None;
+            }
+            match op.destination {
+                Operand::Position(cell) | Operand::Relative(cell) => data[cell] = result,
+                Operand::Immediate(_) => return None,
+            }
+        }
+        Some(data)
+    }
+}
+
+fn random_operand(rng: &mut Rng, writable: bool) -> Operand {
+    let cell = rng.below(DATA_CELLS as u64) as usize;
+    match rng.below(if writable { 2 } else { 3 }) {
+        0 => Operand::Position(cell),
+        1 => Operand::Relative(cell),
+        _ => Operand::Immediate(rng.below(20) as i64 - 5),
+    }
+}
+
+fn random_op(rng: &mut Rng) -> Op {
+    Op {
+        multiply: rng.below(2) == 0,
+        a: random_operand(rng, false),
+        b: random_operand(rng, false),
+        destination: random_operand(rng, true),
+    }
+}
+
+/// A case for which the two engines disagree.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Divergence {
+    pub case: Case,
+    pub interpreter: Result<Vec<i64>, InterpreterError>,
+    pub day2: Result<Vec<i64>, String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "intcode:     {:?}", self.case.intcode())?;
+        writeln!(f, "day 2:       {:?}", self.case.day2_program())?;
+        writeln!(f, "interpreter: {:?}", self.interpreter)?;
+        write!(f, "day2:        {:?}", self.day2)
+    }
+}
+
+/// Runs `case` on both engines, returning their final data cells if they
+/// differ.
+pub fn compare(case: &Case) -> Option<Divergence> {
+    compare_with(case, run_interpreter)
+}
+
+/// Runs an intcode program on the `Interpreter`, returning its memory.
+fn run_interpreter(program: Vec<i64>) -> Result<Vec<i64>, InterpreterError> {
+    let mut interpreter = Interpreter::new(program);
+    interpreter.execute_program()?;
+    Ok(interpreter.memory().to_vec())
+}
+
+/// Like `compare`, with `run` standing in for the `Interpreter`.
+fn compare_with<R>(case: &Case, run: R) -> Option<Divergence>
+where
+    R: Fn(Vec<i64>) -> Result<Vec<i64>, InterpreterError>,
+{
+    let data = case.data_start()..case.data_start() + DATA_CELLS;
+    let interpreter = run(case.intcode()).map(|memory| memory[data].to_vec());
+
+    let data = case.day2_data_start()..case.day2_data_start() + DATA_CELLS;
+    let mut program = case.day2_program();
+    let day2 = day2::reference_program(&mut program)
+        .map(|_| {
+            program[data]
+                .iter()
+                .map(|value| i64::from(*value))
+                .collect()
+        })
+        .map_err(|err| err.to_string());
+
+    match (&interpreter, &day2) {
+        (Ok(a), Ok(b)) if a == b => None,
+        _ => Some(Divergence {
+            case: case.clone(),
+            interpreter,
+            day2,
+        }),
+    }
+}
+
+/// Simplifies `case` for as long as `fails` still holds: drops instructions,
+/// turns operands into position mode, zeroes values and lowers the relative
+/// base to 1.
+pub fn shrink<F: Fn(&Case) -> bool>(mut case: Case, fails: F) -> Case {
+    loop {
+        let smaller = candidates(&case)
+            .into_iter()
+            .find(|candidate| candidate.expected().is_some() && fails(candidate));
+        match smaller {
+            Some(smaller) => case = smaller,
+            None => return case,
+        }
+    }
+}
+
+/// Every case one simplification away from `case`.
+fn candidates(case: &Case) -> Vec<Case> {
+    let mut candidates = Vec::new();
+    for index in 0..case.ops.len() {
+        let mut candidate = case.clone();
+        candidate.ops.remove(index);
+        candidates.push(candidate);
+    }
+    for index in 0..case.ops.len() {
+        let op = case.ops[index];
+        let simpler = |operand: Operand| match operand {
+            Operand::Relative(cell) => Some(Operand::Position(cell)),
+            Operand::Immediate(value) if value != 0 => Some(Operand::Immediate(0)),
+            _ => None,
+        };
+        let mut replace = |op: Op| {
+            let mut candidate = case.clone();
+            candidate.ops[index] = op;
+            candidates.push(candidate);
+        };
+        if op.multiply {
+            replace(Op {
+                multiply: false,
+                ..op
+            });
+        }
+        if let Some(a) = simpler(op.a) {
+            replace(Op { a, ..op });
+        }
+        if let Some(b) = simpler(op.b) {
+            replace(Op { b, ..op });
+        }
+        if let Some(destination) = simpler(op.destination) {
+            replace(Op { destination, ..op });
+        }
+    }
+    if case.base != 1 {
+        candidates.push(Case {
+            base: 1,
+            ..case.clone()
+        });
+    }
+    for index in 0..case.data.len() {
+        if case.data[index] != 0 {
+            let mut candidate = case.clone();
+            candidate.data[index] = 0;
+            candidates.push(candidate);
+        }
+    }
+    candidates
+}
+
+/// Compares `runs` random programs generated from `seed`, returning the
+/// first divergence found, shrunk.
+pub fn fuzz(seed: u64, runs: usize) -> Option<Divergence> {
+    fuzz_with(seed, runs, run_interpreter)
+}
+
+/// Like `fuzz`, with `run` standing in for the `Interpreter`.
+fn fuzz_with<R>(seed: u64, runs: usize, run: R) -> Option<Divergence>
+where
+    R: Fn(Vec<i64>) -> Result<Vec<i64>, InterpreterError>,
+{
+    let mut rng = Rng::new(seed);
+    let case = (0..runs)
+        .map(|_| Case::random(&mut rng))
+        .find(|case| compare_with(case, &run).is_some())?;
+    compare_with(
+        &shrink(case, |case| compare_with(case, &run).is_some()),
+        &run,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn engines_agree() {
+        assert_eq!(None, fuzz(2019, 500));
+    }
+
+    #[test]
+    fn encodings() {
+        let case = Case {
+            base: 10,
+            ops: vec![Op {
+                multiply: true,
+                a: Operand::Relative(1),
+                b: Operand::Immediate(3),
+                destination: Operand::Position(0),
+            }],
+            data: vec![0, 7, 0, 0, 0, 0],
+        };
+        assert_eq!(
+            vec![109, 10, 1202, -2, 3, 7, 99, 0, 7, 0, 0, 0, 0],
+            case.intcode()
+        );
+        assert_eq!(
+            vec![2, 6, 11, 5, 99, 0, 7, 0, 0, 0, 0, 3],
+            case.day2_program()
+        );
+        assert_eq!(Some(vec![21, 7, 0, 0, 0, 0]), case.expected());
+    }
+
+    #[test]
+    fn finds_misread_destination_mode() {
+        // The Interpreter used to take the mode of the destination of add and
+        // mul from the digit of the second parameter
+        let misread = |mut program: Vec<i64>| {
+            let mut pointer = 2;
+            while program[pointer] != 99 {
+                let opcode = program[pointer];
+                program[pointer] = opcode % 10000 + opcode / 1000 % 10 * 10000;
+                pointer += 4;
+            }
+            run_interpreter(program)
+        };
+        let divergence = fuzz_with(2019, 500, misread).unwrap();
+        assert_eq!(1, divergence.case.ops.len());
+    }
+
+    #[test]
+    fn shrinking() {
+        // Pretend that multiplying by an immediate is broken
+        let broken = |case: &Case| {
+            case.ops
+                .iter()
+                .any(|op| op.multiply && matches!(op.b, Operand::Immediate(_)))
+        };
+        let mut rng = Rng::new(7);
+        let case = (0..1000)
+            .map(|_| Case::random(&mut rng))
+            .find(|case| broken(case) && case.ops.len() > 3)
+            .unwrap();
+
+        let shrunk = shrink(case, broken);
+        assert_eq!(1, shrunk.ops.len());
+        assert_eq!(vec![0; DATA_CELLS], shrunk.data);
+        let op = shrunk.ops[0];
+        assert!(op.multiply);
+        assert_eq!(Operand::Immediate(0), op.b);
+        assert!(!matches!(op.a, Operand::Relative(_)));
+    }
+}
@@ -1,4 +1,5 @@
-mod day8;
+use aoc_2019::day8;
+
 fn main() {
     day8::solve2();
 }